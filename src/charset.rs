@@ -0,0 +1,111 @@
+use crate::util;
+
+/// The EBCDIC (code page 037) to Unicode translation table. Non-printable characters are 0.
+const EBCDIC_TABLE: [u16; 256] = [
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+    0x0020, 0x00a0, 0x00e2, 0x00e4, 0x00e0, 0x00e1, 0x00e3, 0x00e5,
+    0x00e7, 0x00f1, 0x00a2, 0x002e, 0x003c, 0x0028, 0x002b, 0x007c,
+    0x0026, 0x00e9, 0x00ea, 0x00eb, 0x00e8, 0x00ed, 0x00ee, 0x00ef,
+    0x00ec, 0x00df, 0x0021, 0x0024, 0x002a, 0x0029, 0x003b, 0x00ac,
+    0x002d, 0x002f, 0x00c2, 0x00c4, 0x00c0, 0x00c1, 0x00c3, 0x00c5,
+    0x00c7, 0x00d1, 0x00a6, 0x002c, 0x0025, 0x005f, 0x003e, 0x003f,
+    0x00f8, 0x00c9, 0x00ca, 0x00cb, 0x00c8, 0x00cd, 0x00ce, 0x00cf,
+    0x00cc, 0x0060, 0x003a, 0x0023, 0x0040, 0x0027, 0x003d, 0x0022,
+    0x00d8, 0x0061, 0x0062, 0x0063, 0x0064, 0x0065, 0x0066, 0x0067,
+    0x0068, 0x0069, 0x00ab, 0x00bb, 0x00f0, 0x00fd, 0x00fe, 0x00b1,
+    0x00b0, 0x006a, 0x006b, 0x006c, 0x006d, 0x006e, 0x006f, 0x0070,
+    0x0071, 0x0072, 0x00aa, 0x00ba, 0x00e6, 0x00b8, 0x00c6, 0x00a4,
+    0x00b5, 0x007e, 0x0073, 0x0074, 0x0075, 0x0076, 0x0077, 0x0078,
+    0x0079, 0x007a, 0x00a1, 0x00bf, 0x00d0, 0x00dd, 0x00de, 0x00ae,
+    0x005e, 0x00a3, 0x00a5, 0x00b7, 0x00a9, 0x00a7, 0x00b6, 0x00bc,
+    0x00bd, 0x00be, 0x005b, 0x005d, 0x00af, 0x00a8, 0x00b4, 0x00d7,
+    0x007b, 0x0041, 0x0042, 0x0043, 0x0044, 0x0045, 0x0046, 0x0047,
+    0x0048, 0x0049, 0x0000, 0x00f4, 0x00f6, 0x00f2, 0x00f3, 0x00f5,
+    0x007d, 0x004a, 0x004b, 0x004c, 0x004d, 0x004e, 0x004f, 0x0050,
+    0x0051, 0x0052, 0x00b9, 0x00fb, 0x00fc, 0x00f9, 0x00fa, 0x00ff,
+    0x005c, 0x00f7, 0x0053, 0x0054, 0x0055, 0x0056, 0x0057, 0x0058,
+    0x0059, 0x005a, 0x00b2, 0x00d4, 0x00d6, 0x00d2, 0x00d3, 0x00d5,
+    0x0030, 0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037,
+    0x0038, 0x0039, 0x00b3, 0x00db, 0x00dc, 0x00d9, 0x00da, 0x0000,
+];
+
+
+/// Character sets used for decoding the bytes in the canonical pane.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Charset {
+    Ascii,
+    Ebcdic,
+    Latin1,
+}
+
+impl Charset {
+    /// Returns the character set with the specified name.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name.to_ascii_lowercase().as_str() {
+            "ascii" => Some(Charset::Ascii),
+            "ebcdic" => Some(Charset::Ebcdic),
+            "latin1" => Some(Charset::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the character set.
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            Charset::Ascii => "ascii",
+            Charset::Ebcdic => "ebcdic",
+            Charset::Latin1 => "latin1",
+        }
+    }
+
+    /// Returns the character set that follows in the cycle.
+    pub fn next(self) -> Self
+    {
+        match self {
+            Charset::Ascii => Charset::Ebcdic,
+            Charset::Ebcdic => Charset::Latin1,
+            Charset::Latin1 => Charset::Ascii,
+        }
+    }
+
+    /// Decodes a byte into a character. Returns None if the character is not printable.
+    pub fn decode(self, byte: u8) -> Option<char>
+    {
+        match self {
+            Charset::Ascii => {
+                if util::check_printable(byte) {
+                    Some(byte as char)
+                } else {
+                    None
+                }
+            },
+            Charset::Ebcdic => match EBCDIC_TABLE[byte as usize] {
+                0 => None,
+                c => std::char::from_u32(c as u32),
+            },
+            Charset::Latin1 => {
+                if util::check_printable(byte) || byte >= 0xa0 {
+                    Some(byte as char)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+
+    /// Encodes a printable character into a byte. Returns None if the character is not in the
+    /// character set.
+    pub fn encode(self, c: char) -> Option<u8>
+    {
+        (0..=u8::MAX).find(|b| self.decode(*b) == Some(c))
+    }
+}
@@ -0,0 +1,430 @@
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use anyhow::bail;
+use crate::util;
+
+/// The size of the chunks read when scanning through the file.
+pub const SCAN_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// The bytes of a file, with the edits that haven't been written to it yet.
+/// The position of the file is the position of the view, which is left to the owner.
+pub struct Document {
+    pub(crate) file: File,
+    pub(crate) edits: HashMap<u64, u8>,
+    read_limit: usize,
+    #[cfg(feature = "mmap")]
+    mmap: bool,
+    #[cfg(feature = "mmap")]
+    map: Option<memmap2::Mmap>
+}
+
+impl Document {
+    /// Returns a new Document. The file is read in chunks of at most the read limit, or mapped into
+    /// memory if mmap is set and the `mmap` feature is enabled.
+    #[cfg_attr(not(feature = "mmap"), allow(unused_variables))]
+    pub fn new(file: File, read_limit: usize, mmap: bool) -> Self
+    {
+        let mut document = Self {
+            file,
+            edits: HashMap::new(),
+            read_limit,
+            #[cfg(feature = "mmap")]
+            mmap,
+            #[cfg(feature = "mmap")]
+            map: None
+        };
+
+        document.remap();
+
+        document
+    }
+
+    /// Returns the maximum number of bytes read at once.
+    pub fn read_limit(&self) -> usize
+    {
+        self.read_limit
+    }
+
+    /// Returns the pending edits.
+    pub fn edits(&self) -> &HashMap<u64, u8>
+    {
+        &self.edits
+    }
+
+    /// Maps the file into memory again, if mapping is enabled. If the file can't be mapped, it is
+    /// read instead.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn remap(&mut self)
+    {
+        // An empty file can't be mapped.
+        self.map = None;
+        if self.mmap && self.len().unwrap_or(0) > 0 {
+            // SAFETY: the mapping is only read, and it is replaced whenever the size of the file
+            // changes through the document. Changes by other processes are not accounted for:
+            // if another process truncates the file, reading the pages past its new end raises
+            // SIGBUS, which kills the editor. This is why mapping is opt-in.
+            self.map = unsafe { memmap2::Mmap::map(&self.file) }.ok();
+        }
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub(crate) fn remap(&mut self)
+    {
+    }
+
+    /// Returns true if the file is mapped into memory.
+    #[cfg(feature = "mmap")]
+    pub fn is_mapped(&self) -> bool
+    {
+        self.map.is_some()
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub fn is_mapped(&self) -> bool
+    {
+        false
+    }
+
+    /// Reads a range of bytes, with the pending edits applied.
+    pub fn read_range(&self, offset: u64, size: usize) -> anyhow::Result<Vec<u8>>
+    {
+        let mut file = &self.file;
+
+        let seek = file.stream_position()?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = util::freadn_to_vec_chunked(&mut file, size, util::LARGE_CHUNK_SIZE)?;
+        file.seek(SeekFrom::Start(seek))?;
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            if let Some(edit) = self.edits.get(&(offset + i as u64)) {
+                *byte = *edit;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// Returns a range of bytes, with the pending edits applied. When the file is mapped and there
+    /// are no edits in the range, the mapped memory is returned without copying it.
+    pub fn scan_range(&self, offset: u64, size: usize) -> anyhow::Result<Cow<'_, [u8]>>
+    {
+        #[cfg(feature = "mmap")]
+        if let Some(map) = &self.map {
+            let end = (offset + size as u64).min(map.len() as u64);
+            let start = offset.min(end);
+            if !self.edits.keys().any(|offset| (start..end).contains(offset)) {
+                return Ok(Cow::Borrowed(&map[start as usize..end as usize]));
+            }
+        }
+
+        Ok(Cow::Owned(self.read_range(offset, size)?))
+    }
+
+    /// Writes a range of bytes [start, end), with the pending edits applied, into a writer.
+    /// The edited or mapped range is read in chunks of at most the read limit. Returns the number
+    /// of bytes written.
+    pub fn write_range<W: Write>(&mut self, start: u64, end: u64, out: &mut W) -> anyhow::Result<u64>
+    {
+        // Without edits in the range, the file can be copied as it is, unless it is mapped.
+        if !self.is_mapped() && !self.edits.keys().any(|offset| (start..end).contains(offset)) {
+            return Ok(util::copy_range(&mut self.file, start, end, out, self.read_limit)?);
+        }
+
+        let mut written = 0;
+        let mut offset = start;
+        while offset < end {
+            let size = (end - offset).min(self.read_limit as u64);
+            let bytes = self.scan_range(offset, size as usize)?;
+            if bytes.is_empty() {
+                break;
+            }
+            out.write_all(&bytes)?;
+            written += bytes.len() as u64;
+            offset += size;
+        }
+
+        Ok(written)
+    }
+
+    /// Returns the size of the file.
+    pub fn len(&self) -> anyhow::Result<u64>
+    {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// Returns true if the file is empty.
+    pub fn is_empty(&self) -> anyhow::Result<bool>
+    {
+        Ok(self.len()? == 0)
+    }
+
+    /// Searches forward for a byte sequence, starting at an offset and wrapping around at the end.
+    /// Returns the offset of the match.
+    pub fn search(&self, needle: &[Option<u8>], from: u64) -> Option<u64>
+    {
+        let end = self.len().ok()?;
+        let from = from.min(end);
+
+        match self.find_forward(needle, from, end) {
+            Some(offset) => Some(offset),
+            None => self.find_forward(needle, 0, (from + needle.len() as u64).saturating_sub(1).min(end)),
+        }
+    }
+
+    /// Searches backward for a byte sequence, starting before an offset and wrapping around at
+    /// the beginning. Returns the offset of the match.
+    pub fn search_backward(&self, needle: &[Option<u8>], from: u64) -> Option<u64>
+    {
+        let end = self.len().ok()?;
+        let from = from.min(end);
+
+        match self.find_backward(needle, 0, (from + needle.len() as u64).saturating_sub(1).min(end)) {
+            Some(offset) => Some(offset),
+            None => self.find_backward(needle, from, end),
+        }
+    }
+
+    /// Compares the bytes against another file, in chunks of at most the read limit. Returns the
+    /// runs of differing bytes, as (start, length). The tail of the longer file counts as
+    /// different. Fails if cancelled returns true between the chunks.
+    pub fn diff<R, C>(&self, other: &mut R, mut cancelled: C) -> anyhow::Result<Vec<(u64, u64)>>
+    where
+        R: Read + Seek,
+        C: FnMut() -> bool
+    {
+        let len = self.len()?;
+        let other_len = other.seek(SeekFrom::End(0))?;
+
+        let mut diffs: Vec<(u64, u64)> = Vec::new();
+        let mut offset = 0;
+        while offset < len.min(other_len) {
+            if cancelled() {
+                bail!("diff cancelled");
+            }
+            let ours = self.scan_range(offset, self.read_limit)?;
+            other.seek(SeekFrom::Start(offset))?;
+            let theirs = util::freadn_to_vec_chunked(other, ours.len(), self.read_limit)?;
+            if theirs.is_empty() {
+                break;
+            }
+            for (i, (a, b)) in ours.iter().zip(theirs.iter()).enumerate() {
+                if a != b {
+                    push_diff(&mut diffs, offset + i as u64, 1);
+                }
+            }
+            offset += theirs.len() as u64;
+        }
+        if len != other_len {
+            push_diff(&mut diffs, offset, len.max(other_len) - offset);
+        }
+
+        Ok(diffs)
+    }
+
+    /// Returns the offset of the first byte after the run of equal bytes at an offset, or the last
+    /// byte of the file if the run reaches the end.
+    pub fn next_run_boundary(&self, from: u64) -> anyhow::Result<u64>
+    {
+        let end = self.len()?;
+        if from + 1 >= end {
+            return Ok(end.saturating_sub(1));
+        }
+
+        let value = self.read_range(from, 1)?[0];
+        let mut chunk_start = from + 1;
+        while chunk_start < end {
+            let size = SCAN_CHUNK_SIZE.min(end - chunk_start);
+            let data = self.read_range(chunk_start, size as usize)?;
+
+            if let Some(i) = data.iter().position(|b| *b != value) {
+                return Ok(chunk_start + i as u64);
+            }
+
+            chunk_start += size;
+        }
+
+        Ok(end - 1)
+    }
+
+    /// Returns the offset of the first non-zero byte after an offset.
+    pub fn next_nonzero(&self, from: u64) -> anyhow::Result<Option<u64>>
+    {
+        let end = self.len()?;
+        let mut chunk_start = from + 1;
+        while chunk_start < end {
+            let size = SCAN_CHUNK_SIZE.min(end - chunk_start);
+            let data = self.scan_range(chunk_start, size as usize)?;
+
+            if let Some(i) = data.iter().position(|b| *b != 0) {
+                return Ok(Some(chunk_start + i as u64));
+            }
+
+            chunk_start += size;
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the offset of the last non-zero byte before an offset.
+    pub fn prev_nonzero(&self, from: u64) -> anyhow::Result<Option<u64>>
+    {
+        let mut chunk_end = from.min(self.len()?);
+        while chunk_end > 0 {
+            let chunk_start = chunk_end.saturating_sub(SCAN_CHUNK_SIZE);
+            let data = self.scan_range(chunk_start, (chunk_end - chunk_start) as usize)?;
+
+            if let Some(i) = data.iter().rposition(|b| *b != 0) {
+                return Ok(Some(chunk_start + i as u64));
+            }
+
+            chunk_end = chunk_start;
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the offset of the start of the run of equal bytes before an offset.
+    pub fn prev_run_boundary(&self, from: u64) -> anyhow::Result<u64>
+    {
+        if from == 0 {
+            return Ok(0);
+        }
+
+        let value = self.read_range(from - 1, 1)?[0];
+        let mut chunk_end = from - 1;
+        while chunk_end > 0 {
+            let chunk_start = chunk_end.saturating_sub(SCAN_CHUNK_SIZE);
+            let data = self.read_range(chunk_start, (chunk_end - chunk_start) as usize)?;
+
+            if let Some(i) = data.iter().rposition(|b| *b != value) {
+                return Ok(chunk_start + i as u64 + 1);
+            }
+
+            chunk_end = chunk_start;
+        }
+
+        Ok(0)
+    }
+
+    /// Returns the offset of the first occurrence of the needle within [start, end).
+    fn find_forward(&self, needle: &[Option<u8>], start: u64, end: u64) -> Option<u64>
+    {
+        let len = needle.len() as u64;
+        if len == 0 {
+            return None;
+        }
+
+        // Consecutive chunks overlap, so that matches spanning two chunks are found.
+        let mut chunk_start = start;
+        while chunk_start + len <= end {
+            let chunk_end = (chunk_start + SCAN_CHUNK_SIZE + len - 1).min(end);
+            let data = self.scan_range(chunk_start, (chunk_end - chunk_start) as usize).ok()?;
+
+            if let Some(i) = data.windows(needle.len()).position(|w| util::matches_pattern(w, needle)) {
+                return Some(chunk_start + i as u64);
+            }
+
+            chunk_start += SCAN_CHUNK_SIZE;
+        }
+
+        None
+    }
+
+    /// Returns the offset of the last occurrence of the needle within [start, end).
+    fn find_backward(&self, needle: &[Option<u8>], start: u64, end: u64) -> Option<u64>
+    {
+        let len = needle.len() as u64;
+        if len == 0 {
+            return None;
+        }
+
+        let mut chunk_end = end;
+        while chunk_end >= start + len {
+            let chunk_start = chunk_end.saturating_sub(SCAN_CHUNK_SIZE + len - 1).max(start);
+            let data = self.scan_range(chunk_start, (chunk_end - chunk_start) as usize).ok()?;
+
+            if let Some(i) = data.windows(needle.len()).rposition(|w| util::matches_pattern(w, needle)) {
+                return Some(chunk_start + i as u64);
+            }
+
+            if chunk_start == start {
+                break;
+            }
+            chunk_end = chunk_start + len - 1;
+        }
+
+        None
+    }
+}
+
+/// Adds a range of differing bytes, merging it with the previous one if they are adjacent.
+fn push_diff(diffs: &mut Vec<(u64, u64)>, start: u64, len: u64)
+{
+    match diffs.last_mut() {
+        Some((prev_start, prev_len)) if *prev_start + *prev_len == start => *prev_len += len,
+        _ => diffs.push((start, len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    /// Returns a document of a temporary file with the bytes, read in chunks of 1 MiB.
+    fn document(bytes: &[u8]) -> Document
+    {
+        let file = util::copy_to_temp_file(&mut Cursor::new(bytes)).unwrap();
+        Document::new(file, 1 << 20, false)
+    }
+
+    #[test]
+    fn diff_past_the_first_chunk()
+    {
+        let ours = vec![0x55; (2 << 20) + 1000];
+        let mut theirs = ours.clone();
+        theirs[(2 << 20) + 10] = 0;
+        theirs[(2 << 20) + 11] = 0;
+        theirs[(1 << 20) + 3] = 0;
+
+        let diffs = document(&ours).diff(&mut Cursor::new(theirs), || false).unwrap();
+        assert_eq!(diffs, vec![((1 << 20) + 3, 1), ((2 << 20) + 10, 2)]);
+    }
+
+    #[test]
+    fn diff_counts_the_longer_tail()
+    {
+        let ours = vec![7; (2 << 20) + 5];
+        let theirs = vec![7; 3 << 20];
+
+        let diffs = document(&ours).diff(&mut Cursor::new(&theirs), || false).unwrap();
+        assert_eq!(diffs, vec![((2 << 20) + 5, (1 << 20) - 5)]);
+
+        let diffs = document(&theirs).diff(&mut Cursor::new(&ours), || false).unwrap();
+        assert_eq!(diffs, vec![((2 << 20) + 5, (1 << 20) - 5)]);
+    }
+
+    #[test]
+    fn diff_is_cancelled()
+    {
+        let doc = document(&[1, 2, 3]);
+        assert!(doc.diff(&mut Cursor::new(vec![1, 2, 3]), || true).is_err());
+        assert_eq!(doc.diff(&mut Cursor::new(vec![1, 2, 3]), || false).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn search_with_wildcards_at_the_ends()
+    {
+        let doc = document(b"ABCABD");
+        let leading = util::parse_pattern("??42").unwrap();
+        assert_eq!(doc.search(&leading, 0), Some(0));
+        assert_eq!(doc.search(&leading, 1), Some(3));
+        assert_eq!(doc.search_backward(&leading, 6), Some(3));
+
+        let trailing = util::parse_pattern("4142??").unwrap();
+        assert_eq!(doc.search(&trailing, 1), Some(3));
+        assert_eq!(doc.search(&util::parse_pattern("44??").unwrap(), 0), None);
+    }
+}
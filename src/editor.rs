@@ -1,14 +1,126 @@
+use std::collections::HashMap;
 use std::fs::File;
-use hex::FromHex;
-use pancurses::Window;
-use anyhow::anyhow;
-use crate::widget::{Direction, HexView};
-use crate::options::Config;
+use std::io::Seek;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use pancurses::{Input, Window};
+use anyhow::{anyhow, bail};
+use crate::widget::{ByteFormat, Direction, Endianness, HexEditingMode, HexView, HighlightScheme, OffsetBase};
+use crate::charset::Charset;
+use crate::hash::Hasher;
+use crate::keymap::{self, Action};
+use crate::options::{self, Config};
+use crate::template::{self, Field};
+use crate::util;
+
+
+/// The maximum number of positions kept in the jumplist.
+const JUMPLIST_SIZE: usize = 100;
+
+/// The number of keys after which the swap file is updated.
+const SWAP_UPDATE_KEYS: u32 = 20;
+
+/// The number of numbered registers, which keep the last yanked and deleted bytes.
+const REGISTER_COUNT: usize = 10;
+
+/// The commands listed in the help, with their descriptions.
+const COMMANDS: [(&str, &str); 35] = [
+    ("q[!]", "Quits, [discarding the unsaved changes]."),
+    ("w[!] [!CMD]", "Writes the changes, [ignoring the read-only mode] [or pipes the file into CMD]."),
+    ("wq[!], x", "Writes the changes and quits."),
+    ("e[dit] PATH", "Opens PATH, or switches to it if it's open."),
+    ("ls, buffers", "Lists the open files."),
+    ("b[uffer] N", "Switches to the Nth open file."),
+    ("bn[ext]", "Switches to the next open file."),
+    ("bp[revious]", "Switches to the previous open file."),
+    ("!CMD", "Filters the selection or the file through CMD."),
+    ("write [START END] PATH", "Writes the selection or the range to PATH."),
+    ("saveas[!] PATH", "Writes the file to PATH, [overwriting it,] and edits PATH from now on."),
+    ("read PATH [OFFSET]", "Writes the contents of PATH over the bytes at OFFSET or the cursor."),
+    ("hash ALGO [START END]", "Shows the crc32, md5 or sha256 hash of the selection, the range, or the file."),
+    ("histogram [START END]", "Shows how often each byte occurs in the selection, the range, or the file."),
+    ("fill [START END] BYTE", "Fills the selection or the range with BYTE."),
+    ("put HEX", "Writes the bytes over the bytes at the cursor."),
+    ("insert COUNT", "Inserts COUNT zero bytes at the cursor."),
+    ("append HEX", "Appends the bytes to the end of the file."),
+    ("truncate SIZE", "Truncates or extends the file to SIZE."),
+    ("highlight none|bytes", "Sets the highlighting scheme."),
+    ("ruler [on|off]", "Shows or hides the ruler."),
+    ("canon [on|off]", "Shows or hides the canonical pane."),
+    ("set OPTION[=VALUE]...", "Sets the options readonly, ruler, offsets, canon, wrap, width, group, charset, offsetbase, endian and signed."),
+    ("offsets [hex|dec|oct|on|off]", "Sets the base of the offsets, or shows or hides them."),
+    ("charset [NAME]", "Sets the character set of the canonical pane."),
+    ("marks", "Lists the marks."),
+    ("registers", "Lists the registers."),
+    ("template [PATH]", "Shows the fields described in PATH under the cursor, or unloads them."),
+    ("noh[lsearch]", "Removes the highlighting of the search matches."),
+    ("diff PATH", "Highlights the differences from PATH."),
+    ("follow [WIDTH] [le|be]", "Goes to the offset stored at the cursor, in the byte order of endian by default."),
+    ("goto OFFSET", "Goes to OFFSET."),
+    ("help", "Shows this help."),
+    ("OFFSET", "Goes to OFFSET."),
+    ("N%", "Goes to N percent of the file."),
+];
 
 
 /// Type of view.
+#[derive(Clone, Copy, PartialEq)]
 pub enum ViewType {
     Hex,
+    Bits,
+}
+
+impl ViewType {
+    /// Returns the next view type, wrapping around.
+    pub fn next(self) -> Self
+    {
+        match self {
+            ViewType::Hex => ViewType::Bits,
+            ViewType::Bits => ViewType::Hex,
+        }
+    }
+
+    /// Returns how the bytes are shown in the view.
+    fn byte_format(self) -> ByteFormat
+    {
+        match self {
+            ViewType::Hex => ByteFormat::Hex,
+            ViewType::Bits => ByteFormat::Bits,
+        }
+    }
+}
+
+
+/// An edit that can be repeated at the cursor with `.`.
+/// Motions, searches, undo and redo, and the edits of the visual selection are not repeatable,
+/// except for filling, which is repeated over the same number of bytes.
+#[derive(Clone)]
+enum Edit {
+    /// Replacing the byte under the cursor.
+    Replace(u8),
+    /// Overwriting bytes, starting at the cursor.
+    Overwrite(Vec<u8>),
+    /// Inserting bytes at the cursor.
+    Insert(Vec<u8>),
+    /// Deleting a number of bytes at the cursor.
+    Delete(u64),
+    /// Filling a number of bytes, starting at the cursor, with a byte.
+    Fill(u8, u64),
+    /// Pasting the last register, either inserting or overwriting.
+    Paste(bool),
+}
+
+
+/// The state of an open file, which is kept while another one is edited.
+struct Buffer {
+    hex_view: HexView,
+    seek: u64,
+    config: Config,
+    jumplist: Vec<u64>,
+    jump_index: usize,
+    marks: HashMap<char, u64>,
+    diffs: Vec<(u64, u64)>,
+    swap_keys: Option<u32>
 }
 
 
@@ -20,18 +132,47 @@ pub struct Editor {
     win: Window,
     cmdline_win: Window,
     config: Config,
-    status: String
+    last_search: Option<Vec<Option<u8>>>,
+    registers: Vec<Vec<u8>>,
+    selected_register: Option<usize>,
+    jumplist: Vec<u64>,
+    jump_index: usize,
+    marks: HashMap<char, u64>,
+    message: Option<String>,
+    last_edit: Option<Edit>,
+    diffs: Vec<(u64, u64)>,
+    count: u32,
+    pending: Option<(Action, u32)>,
+    swap_keys: Option<u32>,
+    buffers: Vec<Option<Buffer>>,
+    current: usize
 }
 
 impl Editor {
     /// Initialises the screen and returns a new Editor.
     pub fn init(file: File, options: Config) -> Self
     {
+        // Needed for drawing non-ASCII characters.
+        ncurses::setlocale(ncurses::LcCategory::all, "");
+
         let win = pancurses::initscr();
         pancurses::raw();
         pancurses::noecho();
         ncurses::set_escdelay(0);
 
+        // Enable all keys.
+        win.keypad(true);
+
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let offset_width = if options.offsets { options.offset_base.width(len) } else { 0 };
+        Self::wait_for_size(&win, <HexView>::min_width(
+            offset_width,
+            options.separator.len() as i32,
+            ByteFormat::Hex,
+            options.group_size as i32,
+            options.bytes_per_line as i32
+        ));
+
         let (y, x) = win.get_max_yx();
 
         let mut editor = Self {
@@ -44,259 +185,2944 @@ impl Editor {
             ),
             cmdline_win: win.derwin(1, x, y - 1, 0)
                 .expect("failed to create a subwin"),
-            status: String::new(),
             seek: 0,
             win,
-            config: options
+            config: options,
+            last_search: None,
+            registers: Vec::new(),
+            selected_register: None,
+            jumplist: Vec::new(),
+            jump_index: 0,
+            marks: HashMap::new(),
+            message: None,
+            last_edit: None,
+            diffs: Vec::new(),
+            count: 0,
+            pending: None,
+            swap_keys: None,
+            buffers: vec![None],
+            current: 0
         };
 
-        // Enable all keys.
-        editor.win.keypad(true);
-
-        editor.status.push_str("-- NORMAL --");
+        // Use colors, if the terminal supports them.
+        if pancurses::has_colors() {
+            pancurses::start_color();
+            pancurses::use_default_colors();
+            editor.hex_view.init_colors();
+        }
 
-        // Seek to the start , draw and refresh the windows.
-        editor.seek(0);
+        // Seek to the start offset, draw and refresh the windows.
+        editor.goto(editor.config.start_offset).ok();
+        if let Some(needle) = editor.config.find.clone() {
+            let from = editor.hex_view.cursor_offset().unwrap_or(0);
+            editor.jump_to_match(&needle, from, true);
+            editor.last_search = Some(needle);
+        }
+        // The initial seek and search are not jumps.
+        editor.jumplist.clear();
+        editor.jump_index = 0;
+        editor.update_jump_position();
         editor.draw();
+        editor.recover_swap();
         editor.refresh();
 
         editor
     }
 
-    /// Replaces the byte under the cursor and writes it to the file.
-    pub fn replace(&mut self) -> anyhow::Result<u64>
+    /// Returns the path of the swap file, or None if the file can't have one.
+    fn swap_path(&self) -> Option<PathBuf>
     {
-        let mut input = String::new();
-
-        // Listen for 2 characters.
-        for _ in 0..2 {
-            match self.win.getch() {
-                Some(pancurses::Input::Character(c)) => {
-                    if c == 0x1b as char {
-                        return Ok(0);
-                    } else {
-                        input.push(c);
-                    }
-                }
-                _ => ()
-            }
+        if self.config.temporary {
+            return None;
         }
 
-        for c in input.chars() {
-            if !c.is_ascii_hexdigit() {
-                return Err(anyhow!("{}: invalid hex digit", c));
+        Some(util::swap_path(&self.config.infile_name))
+    }
+
+    /// Offers to recover the pending edits from a swap file newer than the file. Any other swap
+    /// file is removed.
+    fn recover_swap(&mut self)
+    {
+        let path = match self.swap_path() {
+            Some(p) if p.exists() => p,
+            _ => return,
+        };
+
+        let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+        if modified(&path) > modified(&self.config.infile_name) {
+            self.show_message(&format!("{} found, recover the unsaved changes? (y/n)", path.display()));
+            self.refresh();
+            if let Some(Input::Character('y')) = self.getch() {
+                let recovered = util::read_swap(&path)
+                    .and_then(|edits| self.hex_view.restore_edits(edits));
+                match recovered {
+                    Ok(n) => {
+                        self.show_message(&format!("{} bytes recovered", n));
+                        // Keep the swap file until the recovered edits are written or discarded.
+                        self.swap_keys = Some(0);
+                        return;
+                    },
+                    Err(e) => self.show_message(&e.to_string()),
+                }
             }
         }
 
-        let byte_buf: Vec<u8> = Vec::from_hex(&input)?;
-        self.hex_view.write_byte_at_cursor(byte_buf[0])?;
+        std::fs::remove_file(&path).ok();
+    }
 
-        self.hex_view.read_buf().ok();
-        self.hex_view.draw().ok();
-        self.hex_view.refresh();
+    /// Writes the pending edits to the swap file, on the first change and every SWAP_UPDATE_KEYS
+    /// keys after. The swap file is removed once there are no pending edits.
+    fn update_swap(&mut self)
+    {
+        let path = match self.swap_path() {
+            Some(p) => p,
+            None => return,
+        };
+        let pending = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.is_dirty() && !self.hex_view.document().edits().is_empty()
+            },
+        };
+        if !pending {
+            self.remove_swap();
+            return;
+        }
 
-        Ok(1)
+        match self.swap_keys {
+            Some(keys) if keys + 1 < SWAP_UPDATE_KEYS => {
+                self.swap_keys = Some(keys + 1);
+            },
+            _ => {
+                if let Err(e) = util::write_swap(&path, self.hex_view.document().edits()) {
+                    self.show_message(&format!("{}: {}", path.display(), e));
+                }
+                self.swap_keys = Some(0);
+            },
+        }
     }
 
-    /// Replaces many bytes, until Esc is pressed.
-    pub fn replace_many(&mut self) -> anyhow::Result<()>
+    /// Removes the swap file, once there are no unsaved changes to recover.
+    fn remove_swap(&mut self)
     {
-        let status_backup = self.status.clone();
-
-        self.status.clear();
-        self.status.push_str("-- REPLACE --");
+        if let (Some(path), Some(_)) = (self.swap_path(), self.swap_keys.take()) {
+            std::fs::remove_file(path).ok();
+        }
+    }
 
-        self.cmdline_win.clear();
-        self.cmdline_win.printw(&self.status);
-        let (y, x) = self.hex_view.get_cur_yx();
-        self.win.mv(y, x);
-        self.cmdline_win.refresh();
+    /// Fails and informs the user if the file is read-only, unless they choose to edit a copy of
+    /// it, which is done without asking with `--copy`.
+    fn check_writable(&mut self) -> anyhow::Result<()>
+    {
+        if !self.config.ro {
+            return Ok(());
+        }
+        if self.config.from_stdin {
+            bail!("file is read-only");
+        }
 
-        // Replace bytes until ESC.
-        loop {
-            if self.replace()? == 0 {
-                break;
+        let path = util::copy_path(&self.config.infile_name);
+        if !self.config.copy {
+            self.show_message(&format!("file is read-only, edit a copy in {}? (y/n)", path.display()));
+            self.refresh();
+            if self.getch() != Some(Input::Character('y')) {
+                bail!("file is read-only");
             }
-            self.move_cursor(Direction::Right, 1);
         }
 
-        self.status = status_backup;
-        self.draw();
+        self.save_as(&path, false)
+    }
+
+    /// Listens for two hex digits and returns the byte they form. The first digit is shown at the
+    /// cursor as soon as it is typed. Returns None if Esc is pressed.
+    fn read_hex_byte(&mut self) -> anyhow::Result<Option<u8>>
+    {
+        let high = match self.read_hex_digit()? {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+
+        self.hex_view.set_pending_nibble(Some(high))?;
+        self.hex_view.set_low_nibble(true);
         self.refresh();
+        let low = self.read_hex_digit();
+        self.hex_view.set_low_nibble(false);
+        self.hex_view.set_pending_nibble(None)?;
 
-        Ok(())
+        // Esc reverts the typed nibble.
+        Ok(low?.map(|low| (high << 4) | low))
     }
 
-    /// Invokes the command prompt, listens for keys, and returns teh input.
-    pub fn prompt(&self) -> Option<String>
+    /// Listens for a hex digit and returns its value. Returns None if Esc is pressed.
+    fn read_hex_digit(&self) -> anyhow::Result<Option<u8>>
     {
-        let (y, x) = self.win.get_cur_yx();
-        let mut command = String::new();
+        loop {
+            if let Some(pancurses::Input::Character(c)) = self.win.getch() {
+                if c == 0x1b as char {
+                    return Ok(None);
+                }
 
-        self.cmdline_win.clear();
-        self.win.mv(self.cmdline_win.get_beg_y(), self.cmdline_win.get_beg_x() + 1);
-        self.cmdline_win.addch(':');
-        self.cmdline_win.refresh();
+                return match c.to_digit(16) {
+                    Some(d) => Ok(Some(d as u8)),
+                    None => Err(anyhow!("{}: invalid hex digit", c)),
+                };
+            }
+        }
+    }
 
+    /// Listens for a character and returns its byte in the current character set. Returns None if
+    /// Esc is pressed.
+    fn read_char_byte(&self) -> anyhow::Result<Option<u8>>
+    {
         loop {
-            match self.win.getch() {
-                Some(pancurses::Input::Character(c)) => {
-                    if c == '\n' {
-                        break;
-                    } else if c == 0x1B as char {
-                        command.clear();
-                        break;
-                    } else {
-                        self.cmdline_win.addch(c);
-                        self.cmdline_win.refresh();
-                        command.push(c);
-                    }
-                },
-                Some(pancurses::Input::KeyBackspace) => {
-                    if command.len() < 1 {
-                        break;
-                    }
-
-                    let (cur_y, cur_x) = self.cmdline_win.get_cur_yx();
-                    self.cmdline_win.mv(cur_y, cur_x - 1);
-                    self.cmdline_win.delch();
-                    self.cmdline_win.refresh();
-                    command.pop();
+            if let Some(pancurses::Input::Character(c)) = self.win.getch() {
+                if c == 0x1b as char {
+                    return Ok(None);
                 }
-                Some(_) => (),
-                None => ()
+
+                let charset = self.hex_view.charset();
+                return match charset.encode(c) {
+                    Some(b) => Ok(Some(b)),
+                    None => Err(anyhow!("{}: not a printable {} character", c, charset.name())),
+                };
             }
         }
+    }
 
-        self.cmdline_win.clear();
-        self.cmdline_win.printw(self.status.as_str());
-        self.win.mv(y, x);
-
-        if command.len() > 0 {
-            Some(command)
+    /// Reads a byte in the format of the active pane: two hex digits in the hex pane, or a
+    /// character in the canonical pane. Returns None if Esc is pressed.
+    fn read_byte(&mut self) -> anyhow::Result<Option<u8>>
+    {
+        if self.hex_view.in_canon_pane() {
+            self.read_char_byte()
         } else {
-            None
+            self.read_hex_byte()
         }
     }
 
-    /// Draw the screen.
-    fn draw(&mut self)
+    /// Replaces the byte under the cursor and writes it to the file.
+    pub fn replace(&mut self) -> anyhow::Result<u64>
     {
-        match self.cur_view {
-            ViewType::Hex => {
-                self.hex_view.draw().ok();
-            }
+        match self.replace_once()? {
+            Some(byte) => {
+                self.last_edit = Some(Edit::Replace(byte));
+                Ok(1)
+            },
+            None => Ok(0),
         }
-
-        self.cmdline_win.clear();
-        self.cmdline_win.mv(0, 0);
-        self.cmdline_win.printw(self.status.as_str());
     }
 
-    /// Refresh the screen.
-    pub fn refresh(&self)
+    /// Reads a byte and writes it over the byte under the cursor. Returns the byte, or None if Esc
+    /// is pressed.
+    fn replace_once(&mut self) -> anyhow::Result<Option<u8>>
     {
-        match self.cur_view {
-            ViewType::Hex => {
-                self.hex_view.refresh();
-                // The cursor of the main window needs to be set on every refresh, for some reason.
-                let (y, x) = self.hex_view.get_cur_yx();
-                self.win.mv(y, x);
+        self.check_writable()?;
 
-            },
-        }
+        // On the low nibble, a single digit replaces just the low nibble.
+        let byte = if self.hex_view.on_low_nibble() {
+            let cursor = self.hex_view.cursor_offset()?;
+            let high = self.hex_view.document().read_range(cursor, 1)?.first().map_or(0, |b| b & 0xf0);
+            match self.read_hex_digit()? {
+                Some(low) => high | low,
+                None => return Ok(None),
+            }
+        } else {
+            match self.read_byte()? {
+                Some(b) => b,
+                None => return Ok(None),
+            }
+        };
 
-        self.cmdline_win.refresh();
+        self.hex_view.write_byte_at_cursor(byte)?;
+
+        self.hex_view.draw().ok();
+        self.hex_view.refresh();
+
+        Ok(Some(byte))
     }
 
-    /// Finish
-    pub fn end(&self)
+    /// Replaces many bytes, until Esc is pressed.
+    pub fn replace_many(&mut self) -> anyhow::Result<()>
     {
-        pancurses::endwin();
+        self.check_writable()?;
+
+        self.hex_view.set_editing_mode(HexEditingMode::Replace);
+
+        self.draw_cmdline();
+        let (y, x) = self.hex_view.get_cur_yx();
+        self.win.mv(y, x);
+        self.cmdline_win.refresh();
+
+        // Replace bytes until ESC. An error ends the mode as well.
+        let mut bytes = Vec::new();
+        let result = loop {
+            let byte = match self.replace_once() {
+                Ok(Some(b)) => b,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            bytes.push(byte);
+            // Move on to the next byte, not its low nibble.
+            self.hex_view.set_low_nibble(true);
+            self.move_cursor(Direction::Right, 1);
+
+            // Keep the mode indicator, and put the cursor back into the hex view.
+            self.draw_cmdline();
+            self.refresh();
+        };
+        if !bytes.is_empty() {
+            self.last_edit = Some(Edit::Overwrite(bytes));
+        }
+
+        self.hex_view.set_editing_mode(HexEditingMode::Normal);
+        self.draw();
+        self.refresh();
+
+        result
     }
 
-    /// Return the width of the entire screen.
-    pub fn width(&self) -> i32
+    /// Invokes a prompt for an offset and goes to it. Only the digits of an offset can be typed,
+    /// and its value is shown as it is typed.
+    pub fn goto_prompt(&mut self) -> anyhow::Result<()>
     {
-        self.win.get_max_x()
+        let offset = match self.prompt_with('@', util::is_partial_offset, Self::show_offset_value) {
+            Some(o) => util::parse_offset(&o)?,
+            None => return Ok(()),
+        };
+
+        self.goto(offset)
     }
 
-    /// Return the width of the entire screen.
-    pub fn height(&self) -> i32
+    /// Shows the value of the offset typed in the prompt, after it.
+    fn show_offset_value(&mut self, text: &str)
     {
-        self.win.get_max_y()
+        let (cur_y, cur_x) = self.cmdline_win.get_cur_yx();
+        self.cmdline_win.clrtoeol();
+        if let Ok(offset) = util::parse_offset(text) {
+            self.cmdline_win.printw(format!("  = {} ({:#x})", offset, offset));
+        }
+        self.cmdline_win.mv(cur_y, cur_x);
+        self.cmdline_win.refresh();
     }
 
-    /// Listen for an input event.
-    pub fn getch(&self) -> Option<pancurses::Input>
+    /// Invokes a prompt for a hex string and writes its bytes over the bytes at the cursor.
+    /// Nothing is written if the string is not valid.
+    pub fn replace_string(&mut self) -> anyhow::Result<()>
     {
-        self.win.getch()
+        self.check_writable()?;
+
+        let hex_string = match self.prompt('#') {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        let bytes = util::parse_hex_bytes(&hex_string)?;
+
+        self.put(&bytes)
     }
 
-    /// Move the cursor.
-    pub fn move_cursor(&mut self, direction: Direction, count: i32)
+    /// Deletes count bytes starting at the cursor, shrinking the file.
+    pub fn delete(&mut self, count: u64) -> anyhow::Result<()>
     {
+        self.check_writable()?;
+        self.check_resizable()?;
+
         match self.cur_view {
-            ViewType::Hex => {
-                match self.hex_view.move_cursor(direction, count) {
-                    Err(_) => (),
-                    Ok(v) => self.seek = v
-                }
-                // Again, the cursor of the main window needs to be reset.
+            ViewType::Hex | ViewType::Bits => {
+                // The deleted bytes are kept in the registers.
+                let offset = self.hex_view.cursor_offset()?;
+                let len = self.hex_view.document().len()?;
+                let bytes = self.hex_view.document().read_range(offset, count.min(len.saturating_sub(offset)) as usize)?;
+                self.hex_view.delete_bytes_at_cursor(count)?;
+                self.push_register(bytes);
+                self.last_edit = Some(Edit::Delete(count));
+                self.seek = self.hex_view.get_seek()?;
                 let (y, x) = self.hex_view.get_cur_yx();
                 self.win.mv(y, x);
-            }
+            },
         }
+
+        Ok(())
     }
 
-    /// Seek - jump to a 16-byte aligned offset, advancing the cursor properly.
-    /// Accepts both positive and negative values - if negative, start from the end.
-    pub fn seek(&mut self, offset: i64)
+    /// Inserts bytes at the cursor, growing the file, until Esc is pressed.
+    pub fn insert_many(&mut self) -> anyhow::Result<()>
     {
-        match self.cur_view {
-            ViewType::Hex => {
-                match self.hex_view.seek(offset) {
-                    Err(_) => return (),
-                    Ok(_) => ()
-                }
-                self.seek = offset as u64;
-                let (y, x) = self.hex_view.get_cur_yx();
-                self.win.mv(y, x);
+        self.check_writable()?;
+        self.check_resizable()?;
+
+        self.hex_view.set_editing_mode(HexEditingMode::Insert);
+        // Whole bytes are inserted.
+        self.hex_view.set_low_nibble(false);
+
+        self.draw_cmdline();
+        let (y, x) = self.hex_view.get_cur_yx();
+        self.win.mv(y, x);
+        self.cmdline_win.refresh();
+
+        // Insert bytes until ESC.
+        let mut bytes = Vec::new();
+        let result = loop {
+            let byte = match self.read_byte() {
+                Ok(Some(b)) => b,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            if let Err(e) = self.hex_view.insert_byte_at_cursor(byte) {
+                break Err(e);
             }
+            bytes.push(byte);
+            // Move on to the next byte, not its low nibble.
+            self.hex_view.set_low_nibble(true);
+            self.move_cursor(Direction::Right, 1);
+            self.refresh();
+        };
+        if !bytes.is_empty() {
+            self.last_edit = Some(Edit::Insert(bytes));
         }
-    }
 
-    /// Scrolls the view up and down.
-    pub fn scroll(&mut self, direction: Direction, count: u32)
-    {
-        match self.cur_view {
-            ViewType::Hex => {
-                self.hex_view.scroll(direction, count).ok();
-            },
-        }
+        self.hex_view.set_editing_mode(HexEditingMode::Normal);
+        self.draw();
+        self.refresh();
+
+        result
     }
 
-    /// Switches the active pane of the current view.
-    pub fn switch_pane(&mut self)
+    /// Invokes the command prompt and executes the entered command.
+    /// Returns true if the editor should quit.
+    pub fn command(&mut self) -> bool
     {
-        match self.cur_view {
-            ViewType::Hex => {
-                self.hex_view.switch_pane().ok();
-            },
+        let command = match self.prompt(':') {
+            Some(c) => c,
+            None => return false,
+        };
+
+        match self.execute(command.trim()) {
+            Ok(quit) => quit,
+            Err(e) => {
+                self.show_message(&e.to_string());
+                false
+            }
         }
     }
 
-    // TODO
-    /// Switches the view.
-    pub fn switch_view(&mut self)
+    /// Parses a command and dispatches it to its handler.
+    /// Returns true if the editor should quit.
+    fn execute(&mut self, command: &str) -> anyhow::Result<bool>
     {
-        match self.cur_view {
-            ViewType::Hex => {
-                self.cur_view = ViewType::Hex;
-            },
+        // Filtering through a shell command.
+        if let Some(shell_command) = command.strip_prefix('!') {
+            self.pipe(shell_command.trim(), true)?;
+            return Ok(false);
         }
 
-        self.draw();
-        self.refresh();
+        let mut args = command.split_whitespace();
+        let name = match args.next() {
+            Some(n) => n,
+            None => return Ok(false),
+        };
+
+        match name {
+            "q" | "q!" => self.quit(name.ends_with('!')),
+            "w" | "w!" => {
+                // Piping into a shell command.
+                if let Some(shell_command) = command[name.len()..].trim().strip_prefix('!') {
+                    self.pipe(shell_command.trim(), false)?;
+                    return Ok(false);
+                }
+                self.write(name.ends_with('!'))?;
+                Ok(false)
+            },
+            "e" | "edit" => {
+                let path = match args.next() {
+                    Some(p) => p,
+                    None => bail!("{}: missing file name", name),
+                };
+                self.edit(Path::new(path))?;
+                Ok(false)
+            },
+            "ls" | "buffers" => {
+                self.list_buffers();
+                Ok(false)
+            },
+            "b" | "buffer" => {
+                let n: usize = match args.next().map(str::parse) {
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => bail!("{}: expected a buffer number", name),
+                    None => bail!("{}: missing buffer number", name),
+                };
+                if n == 0 || n > self.buffers.len() {
+                    bail!("E86: Buffer {} does not exist", n);
+                }
+                self.switch_buffer(n - 1);
+                Ok(false)
+            },
+            "bn" | "bnext" => {
+                self.switch_buffer((self.current + 1) % self.buffers.len());
+                Ok(false)
+            },
+            "bp" | "bprevious" => {
+                self.switch_buffer((self.current + self.buffers.len() - 1) % self.buffers.len());
+                Ok(false)
+            },
+            "wq" | "wq!" | "x" => {
+                self.write(name.ends_with('!'))?;
+                Ok(true)
+            },
+            "highlight" => {
+                let scheme = match args.next() {
+                    Some("none") => HighlightScheme::None,
+                    Some("bytes") => HighlightScheme::ByteClass,
+                    Some(s) => bail!("{}: invalid highlight scheme", s),
+                    None => bail!("highlight: missing scheme"),
+                };
+                self.set_highlight_scheme(scheme)?;
+                Ok(false)
+            },
+            "write" => {
+                let args: Vec<&str> = args.collect();
+                let (range, path) = match args.as_slice() {
+                    [start, end, path] => (Some((util::parse_offset(start)?, util::parse_offset(end)?)), path),
+                    [path] => (None, path),
+                    _ => bail!("usage: write [START END] PATH"),
+                };
+                self.export(range, Path::new(path))?;
+                Ok(false)
+            },
+            "saveas" | "saveas!" => {
+                let path = match args.next() {
+                    Some(p) => p,
+                    None => bail!("saveas: missing file name"),
+                };
+                self.save_as(Path::new(path), name.ends_with('!'))?;
+                Ok(false)
+            },
+            "read" => {
+                let path = match args.next() {
+                    Some(p) => p,
+                    None => bail!("read: missing file name"),
+                };
+                let at = match args.next() {
+                    Some(o) => Some(util::parse_offset(o)?),
+                    None => None,
+                };
+                self.import(Path::new(path), at)?;
+                Ok(false)
+            },
+            "hash" => {
+                let args: Vec<&str> = args.collect();
+                let (name, range) = match args.as_slice() {
+                    [name, start, end] => (name, Some((util::parse_offset(start)?, util::parse_offset(end)?))),
+                    [name] => (name, None),
+                    _ => bail!("usage: hash crc32|md5|sha256 [START END]"),
+                };
+                let hasher = match Hasher::from_name(name) {
+                    Some(h) => h,
+                    None => bail!("{}: expected crc32, md5 or sha256", name),
+                };
+                self.hash(hasher, range)?;
+                Ok(false)
+            },
+            "histogram" => {
+                let args: Vec<&str> = args.collect();
+                let range = match args.as_slice() {
+                    [start, end] => Some((util::parse_offset(start)?, util::parse_offset(end)?)),
+                    [] => None,
+                    _ => bail!("usage: histogram [START END]"),
+                };
+                self.histogram(range)?;
+                Ok(false)
+            },
+            "fill" => {
+                let args: Vec<&str> = args.collect();
+                let (range, byte) = match args.as_slice() {
+                    [start, end, byte] => (Some((util::parse_offset(start)?, util::parse_offset(end)?)), byte),
+                    [byte] => (None, byte),
+                    _ => bail!("usage: fill [START END] BYTE"),
+                };
+                let byte = match u8::from_str_radix(byte.trim_start_matches("0x"), 16) {
+                    Ok(b) => b,
+                    Err(_) => bail!("{}: invalid byte", byte),
+                };
+                self.fill(range, byte)?;
+                Ok(false)
+            },
+            "put" => {
+                let bytes = util::parse_hex_bytes(&args.collect::<String>())?;
+                self.put(&bytes)?;
+                Ok(false)
+            },
+            "truncate" => {
+                let size = match args.next() {
+                    Some(s) => util::parse_offset(s)?,
+                    None => bail!("truncate: missing size"),
+                };
+                self.truncate(size)?;
+                Ok(false)
+            },
+            "insert" => {
+                let count = match args.next() {
+                    Some(c) => util::parse_offset(c)?,
+                    None => bail!("insert: missing count"),
+                };
+                self.insert_zeros(count)?;
+                Ok(false)
+            },
+            "append" => {
+                let bytes = util::parse_hex_bytes(&args.collect::<String>())?;
+                self.append(&bytes)?;
+                Ok(false)
+            },
+            "ruler" => {
+                let ruler = match args.next() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(s) => bail!("{}: expected on or off", s),
+                    None => !self.hex_view.has_ruler(),
+                };
+                self.set_ruler(ruler)?;
+                Ok(false)
+            },
+            "canon" => {
+                let canon = match args.next() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(s) => bail!("{}: expected on or off", s),
+                    None => !self.hex_view.has_canon(),
+                };
+                self.set_canon(canon)?;
+                Ok(false)
+            },
+            "set" => {
+                let settings: Vec<&str> = args.collect();
+                if settings.is_empty() {
+                    bail!("usage: set OPTION[=VALUE]...");
+                }
+                for setting in settings {
+                    self.set_option(setting)?;
+                }
+                Ok(false)
+            },
+            "offsets" => {
+                let base = match args.next() {
+                    Some("on") => return self.set_offsets(true).map(|_| false),
+                    Some("off") => return self.set_offsets(false).map(|_| false),
+                    Some(name) => match OffsetBase::from_name(name) {
+                        Some(b) => b,
+                        None => bail!("{}: expected hex, dec, oct, on or off", name),
+                    },
+                    None => self.hex_view.offset_base().next(),
+                };
+                self.set_offset_base(base)?;
+                Ok(false)
+            },
+            "charset" => {
+                let charset = match args.next() {
+                    Some(name) => match Charset::from_name(name) {
+                        Some(c) => c,
+                        None => bail!("{}: unknown character set", name),
+                    },
+                    None => self.hex_view.charset().next(),
+                };
+                self.set_charset(charset)?;
+                Ok(false)
+            },
+            "marks" => {
+                self.list_marks()?;
+                Ok(false)
+            },
+            "registers" => {
+                self.list_registers()?;
+                Ok(false)
+            },
+            "noh" | "nohlsearch" => {
+                self.clear_search_highlight()?;
+                Ok(false)
+            },
+            "help" => {
+                self.help();
+                Ok(false)
+            },
+            "template" => {
+                let fields = match args.next() {
+                    Some(path) => template::read_template(Path::new(path))?,
+                    None => Vec::new(),
+                };
+                self.set_template(fields);
+                Ok(false)
+            },
+            "diff" => {
+                let path = match args.next() {
+                    Some(p) => p,
+                    None => bail!("diff: missing file name"),
+                };
+                self.diff(Path::new(path))?;
+                Ok(false)
+            },
+            "follow" => {
+                let width = match args.next() {
+                    Some(w) => match w.parse() {
+                        Ok(w) => w,
+                        Err(_) => bail!("{}: invalid width", w),
+                    },
+                    None => 4,
+                };
+                let endianness = match args.next() {
+                    Some(s) => match Endianness::from_name(s) {
+                        Some(e) => e,
+                        None => bail!("{}: expected le or be", s),
+                    },
+                    None => self.hex_view.endianness(),
+                };
+                self.follow(width, endianness == Endianness::Little)?;
+                Ok(false)
+            },
+            "goto" => {
+                let offset = match args.next() {
+                    Some(o) => util::parse_offset(o)?,
+                    None => bail!("goto: missing offset"),
+                };
+                self.goto(offset)?;
+                Ok(false)
+            },
+            _ if name.ends_with('%') => {
+                let percent = match name[..name.len() - 1].parse() {
+                    Ok(p) if p <= 100 => p,
+                    _ => bail!("{}: expected a percentage from 0 to 100", name),
+                };
+                self.goto_percent(percent)?;
+                Ok(false)
+            },
+            _ => {
+                // A bare number is a jump to that offset.
+                let offset = match util::parse_offset(name) {
+                    Ok(o) => o,
+                    Err(_) => bail!("{}: not an editor command", name),
+                };
+                self.goto(offset)?;
+                Ok(false)
+            },
+        }
+    }
+
+    /// Writes the bytes in the range [start, end) to a new file.
+    /// Without a range, the visual selection is written.
+    pub fn export(&mut self, range: Option<(u64, u64)>, path: &Path) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let (start, end) = self.resolve_range(range)?;
+                self.check_destination(path)?;
+                let document = self.hex_view.document_mut();
+                let (_, written) = util::write_new_file(path, true, |out| document.write_range(start, end, out))?;
+
+                self.show_message(&format!("\"{}\" {}B written", path.display(), written));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Writes the contents of a file over the bytes at an offset, or at the cursor, growing the
+    /// file if they don't fit.
+    pub fn import(&mut self, path: &Path, at: Option<u64>) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => bail!("{}: {}", path.display(), e),
+        };
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let offset = match at {
+                    Some(o) => o,
+                    None => self.hex_view.cursor_offset()?,
+                };
+                let len = self.hex_view.document().len()?;
+                if offset > len {
+                    bail!("{:#x}: past the end of the file", offset);
+                }
+                // The size of a pipe or a device isn't known until it's read, so it may grow the file.
+                let meta = file.metadata()?;
+                if !meta.is_file() || offset.saturating_add(meta.len()) > len {
+                    self.check_resizable()?;
+                }
+
+                let (read, appended) = self.hex_view.write_from_reader(&mut file, offset)?;
+                self.draw();
+
+                let mut message = format!("\"{}\" {}B read", path.display(), read);
+                if appended > 0 {
+                    message.push_str(&format!(", file extended by {}B", appended));
+                }
+                self.show_message(&message);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Hashes the range [start, end), the selection, or the whole file, and shows the hash.
+    pub fn hash(&mut self, mut hasher: Hasher, range: Option<(u64, u64)>) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let (start, end) = match range {
+                    Some(_) => self.resolve_range(range)?,
+                    None => match self.hex_view.selection()? {
+                        Some((start, end)) => (start, end + 1),
+                        None => (0, self.hex_view.document().len()?),
+                    },
+                };
+                // Hashed in chunks, so that it can be cancelled.
+                let mut hashed = 0;
+                let mut offset = start;
+                while offset < end {
+                    if self.check_cancel() {
+                        bail!("hash cancelled");
+                    }
+                    let chunk_end = end.min(offset + self.config.read_limit as u64);
+                    hashed += self.hex_view.document_mut().write_range(offset, chunk_end, &mut hasher)?;
+                    offset = chunk_end;
+                }
+
+                self.show_message(&format!("{} {}  ({}B)", hasher.name(), hasher.finish(), hashed));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Counts how often each byte occurs in the range [start, end), the selection, or the file, and
+    /// shows the counts as a 16x16 grid of levels in an overlay, with the lowest, highest and most
+    /// common bytes, and how many are printable.
+    pub fn histogram(&mut self, range: Option<(u64, u64)>) -> anyhow::Result<()>
+    {
+        const LEVELS: &[u8] = b" .:-=+*#%@";
+
+        let mut counts = [0u64; 256];
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let (start, end) = match range {
+                    Some(_) => self.resolve_range(range)?,
+                    None => match self.hex_view.selection()? {
+                        Some((start, end)) => (start, end + 1),
+                        None => (0, self.hex_view.document().len()?),
+                    },
+                };
+
+                let mut offset = start;
+                while offset < end {
+                    if self.check_cancel() {
+                        bail!("histogram cancelled");
+                    }
+                    let size = (end - offset).min(self.config.read_limit as u64) as usize;
+                    let bytes = self.hex_view.document().scan_range(offset, size)?;
+                    if bytes.is_empty() {
+                        break;
+                    }
+                    for byte in bytes.iter() {
+                        counts[*byte as usize] += 1;
+                    }
+                    offset += bytes.len() as u64;
+                }
+            },
+        }
+
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            bail!("no bytes to count");
+        }
+        let present = || (0..=255u8).filter(|b| counts[*b as usize] > 0);
+        let lowest = present().next().unwrap_or(0);
+        let highest = present().next_back().unwrap_or(0);
+        let most_common = (0..=255u8).max_by_key(|b| counts[*b as usize]).unwrap_or(0);
+        let max = counts[most_common as usize];
+        let printable: u64 = (0..=255u8).filter(|b| util::check_printable(*b)).map(|b| counts[b as usize]).sum();
+        let percent = |n: u64| n as f64 * 100.0 / total as f64;
+
+        // Any byte that occurs gets at least the lowest visible level.
+        let level = |count: u64| LEVELS[(count * (LEVELS.len() as u64 - 1)).div_ceil(max) as usize] as char;
+
+        let mut lines = vec![
+            format!("histogram of {}B, relative to the most common byte", total),
+            format!("      {}", (0..16).map(|x| format!("{:x} ", x)).collect::<String>()),
+        ];
+        for row in 0..16 {
+            let cells: String = (0..16).map(|x| format!("{} ", level(counts[row * 16 + x]))).collect();
+            lines.push(format!("  {:x}0  {}", row, cells));
+        }
+        lines.push(String::new());
+        lines.push(format!(
+            "lowest {:#04x}  highest {:#04x}  most common {:#04x} ({})",
+            lowest,
+            highest,
+            most_common,
+            max
+        ));
+        lines.push(format!(
+            "printable {} ({:.1}%)  non-printable {} ({:.1}%)",
+            printable,
+            percent(printable),
+            total - printable,
+            percent(total - printable)
+        ));
+
+        self.show_overlay(&lines);
+
+        Ok(())
+    }
+
+    /// Returns the range [start, end) if given, or the selection.
+    fn resolve_range(&self, range: Option<(u64, u64)>) -> anyhow::Result<(u64, u64)>
+    {
+        let (start, end) = match range {
+            Some(r) => r,
+            None => match self.hex_view.selection()? {
+                Some((start, end)) => (start, end + 1),
+                None => bail!("no range given and nothing selected"),
+            },
+        };
+        if end < start {
+            bail!("the end of the range is before its start");
+        }
+        if end > self.hex_view.document().len()? {
+            bail!("{:#x}: past the end of the file", end);
+        }
+
+        Ok((start, end))
+    }
+
+    /// Fills the range [start, end), or the selection, with a byte.
+    pub fn fill(&mut self, range: Option<(u64, u64)>, byte: u8) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let (start, end) = self.resolve_range(range)?;
+                let count = self.hex_view.fill(start, end, byte)?;
+                self.last_edit = Some(Edit::Fill(byte, end - start));
+                self.cancel_visual()?;
+
+                self.show_message(&format!("{} bytes filled", count));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Writes bytes at the cursor and moves the cursor past them.
+    pub fn put(&mut self, bytes: &[u8]) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let cursor = self.hex_view.cursor_offset()?;
+                let count = self.hex_view.write_bytes_at_offset(bytes, cursor)?;
+                self.last_edit = Some(Edit::Overwrite(bytes.to_vec()));
+
+                // Stay on the last byte of the file.
+                let len = self.hex_view.document().len()?;
+                self.seek = self.hex_view.set_cursor_offset((cursor + count as u64).min(len - 1))?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+
+                self.show_message(&format!("{} bytes written", count));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Appends bytes to the end of the file and moves the cursor to them.
+    pub fn append(&mut self, bytes: &[u8]) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+        self.check_resizable()?;
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let cursor = self.hex_view.cursor_offset()?;
+                let offset = self.hex_view.append_bytes(bytes)?;
+                self.seek = self.hex_view.set_cursor_offset(offset)?;
+                self.push_jump(cursor);
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+
+                self.show_message(&format!("{} bytes appended", bytes.len()));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Inserts count zero bytes at the cursor. The cursor stays on the first inserted byte.
+    pub fn insert_zeros(&mut self, count: u64) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+        self.check_resizable()?;
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let offset = self.hex_view.cursor_offset()?;
+                self.hex_view.insert_zeros_at_offset(offset, count)?;
+                self.seek = self.hex_view.set_cursor_offset(offset)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+
+                self.show_message(&format!("{} bytes inserted", count));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Shrinks or grows the file to a size.
+    pub fn truncate(&mut self, size: u64) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+        if size != self.hex_view.document().len()? {
+            self.check_resizable()?;
+        }
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.truncate(size)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+
+                self.show_message(&format!("truncated to {}B", size));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Inserts bytes at the cursor and moves the cursor past them.
+    fn insert(&mut self, bytes: &[u8]) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+        self.check_resizable()?;
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let cursor = self.hex_view.cursor_offset()?;
+                let count = self.hex_view.insert_bytes_at_offset(bytes, cursor)?;
+                self.last_edit = Some(Edit::Insert(bytes.to_vec()));
+
+                // Stay on the last byte of the file.
+                let len = self.hex_view.document().len()?;
+                self.seek = self.hex_view.set_cursor_offset((cursor + count as u64).min(len - 1))?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Repeats the last edit at the cursor.
+    pub fn repeat(&mut self) -> anyhow::Result<()>
+    {
+        let edit = match &self.last_edit {
+            Some(e) => e.clone(),
+            None => bail!("no previous edit"),
+        };
+
+        match edit {
+            Edit::Replace(byte) => {
+                self.check_writable()?;
+                self.hex_view.write_byte_at_cursor(byte)?;
+                self.hex_view.draw()?;
+            },
+            Edit::Overwrite(bytes) => self.put(&bytes)?,
+            Edit::Insert(bytes) => self.insert(&bytes)?,
+            Edit::Delete(count) => self.delete(count)?,
+            Edit::Fill(byte, count) => {
+                let cursor = self.hex_view.cursor_offset()?;
+                self.fill(Some((cursor, cursor + count)), byte)?;
+            },
+            Edit::Paste(insert) => self.paste(insert)?,
+        }
+
+        Ok(())
+    }
+
+    /// Shows or hides the ruler above the panes.
+    pub fn set_ruler(&mut self, ruler: bool) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.set_ruler(ruler)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Sets the fields of the template shown for the bytes under the cursor. No fields unload the
+    /// template.
+    pub fn set_template(&mut self, fields: Vec<Field>)
+    {
+        let count = fields.len();
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => self.hex_view.set_template(fields),
+        }
+        self.draw();
+
+        if count > 0 {
+            self.show_message(&format!("{} fields loaded", count));
+        }
+    }
+
+    /// Shows or hides the offset column.
+    pub fn set_offsets(&mut self, offsets: bool) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.set_offsets(offsets)?;
+                self.config.offsets = offsets;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Shows or hides the canonical pane.
+    pub fn set_canon(&mut self, canon: bool) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.set_canon(canon)?;
+                self.config.canon = canon;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Makes the cursor wrap around the ends of the file, instead of stopping at them.
+    pub fn set_wrap(&mut self, wrap: bool)
+    {
+        self.config.wrap = wrap;
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => self.hex_view.set_wrap(wrap),
+        }
+    }
+
+    /// Sets a runtime option: `NAME=VALUE`, `NAME` to turn it on or show its value, or `noNAME` to
+    /// turn it off.
+    pub fn set_option(&mut self, setting: &str) -> anyhow::Result<()>
+    {
+        let (name, value) = match setting.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (setting, None),
+        };
+
+        match (name, value) {
+            ("readonly" | "ro", None) => self.set_readonly(true)?,
+            ("noreadonly" | "noro", None) => self.set_readonly(false)?,
+            ("ruler", None) => self.set_ruler(true)?,
+            ("noruler", None) => self.set_ruler(false)?,
+            ("offsets", None) => self.set_offsets(true)?,
+            ("nooffsets", None) => self.set_offsets(false)?,
+            ("canon", None) => self.set_canon(true)?,
+            ("nocanon", None) => self.set_canon(false)?,
+            ("signed", None) => self.set_signed(true)?,
+            ("nosigned", None) => self.set_signed(false)?,
+            ("wrap", None) => self.set_wrap(true),
+            ("nowrap", None) => self.set_wrap(false),
+            ("width", Some(value)) => {
+                let width = match value.parse() {
+                    Ok(w) if w > 0 => w,
+                    _ => bail!("{}: invalid number of bytes per line", value),
+                };
+                match self.cur_view {
+                    ViewType::Hex | ViewType::Bits => {
+                        self.seek = self.hex_view.set_bytes_per_line(width)?;
+                        self.config.bytes_per_line = width as usize;
+                    },
+                }
+            },
+            ("group", Some(value)) => {
+                let group_size = options::parse_group_size(value)?;
+                match self.cur_view {
+                    ViewType::Hex | ViewType::Bits => {
+                        self.seek = self.hex_view.set_group_size(group_size as i32)?;
+                        self.config.group_size = group_size;
+                    },
+                }
+            },
+            ("charset", Some(value)) => match Charset::from_name(value) {
+                Some(c) => self.set_charset(c)?,
+                None => bail!("{}: unknown character set", value),
+            },
+            ("offsetbase", Some(value)) => match OffsetBase::from_name(value) {
+                Some(b) => self.set_offset_base(b)?,
+                None => bail!("{}: expected hex, dec or oct", value),
+            },
+            ("width", None) => self.show_message(&format!("width={}", self.hex_view.line_width())),
+            ("group", None) => self.show_message(&format!("group={}", self.hex_view.group_size())),
+            ("charset", None) => self.show_message(&format!("charset={}", self.hex_view.charset().name())),
+            ("endian", Some(value)) => match Endianness::from_name(value) {
+                Some(e) => self.set_endianness(e)?,
+                None => bail!("{}: expected le or be", value),
+            },
+            ("endian", None) => self.show_message(&format!("endian={}", self.hex_view.endianness().name())),
+            ("offsetbase", None) => self.show_message(&format!("offsetbase={}", self.hex_view.offset_base().name())),
+            _ => bail!("E518: Unknown option: {}", setting),
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure that the file can be resized. With an output file, the input is only read, so it
+    /// is copied into a temporary file, with the pending edits, before the first change of its size.
+    fn check_resizable(&mut self) -> anyhow::Result<()>
+    {
+        if self.config.output.is_none() || self.config.temporary {
+            return Ok(());
+        }
+
+        if let Err(e) = self.hex_view.copy_file() {
+            bail!("{}: failed to copy the file: {}", self.config.infile_name.display(), e);
+        }
+        // The swap file can't describe the changes of the size of the copy.
+        self.remove_swap();
+        self.config.temporary = true;
+
+        Ok(())
+    }
+
+    /// Fails if the file is a temporary copy of the input, which can't be written back.
+    fn check_written_back(&self) -> anyhow::Result<()>
+    {
+        if !self.config.temporary {
+            return Ok(());
+        }
+
+        if self.config.from_stdin {
+            bail!("standard input can't be written back (use :write PATH)");
+        }
+        bail!("{}: can't be seeked, so it can't be written back (use :write PATH)", self.config.infile_name.display());
+    }
+
+    /// Makes the file read-only, or read-write, reopening it if needed.
+    fn set_readonly(&mut self, ro: bool) -> anyhow::Result<()>
+    {
+        // With an output file, the input is never written.
+        if !ro && self.config.ro && self.config.output.is_none() {
+            self.check_written_back()?;
+            self.hex_view.reopen(&self.config.infile_name)?;
+        }
+
+        self.config.ro = ro;
+        self.hex_view.set_readonly(ro);
+        self.draw();
+
+        Ok(())
+    }
+
+    /// Sets the base in which the offsets are shown.
+    pub fn set_offset_base(&mut self, base: OffsetBase) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.set_offset_base(base)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Pipes the selection, or the visible bytes, into a shell command.
+    /// If filtering, the bytes are replaced with the output of the command. Otherwise, the output
+    /// is displayed.
+    pub fn pipe(&mut self, shell_command: &str, filter: bool) -> anyhow::Result<()>
+    {
+        if shell_command.is_empty() {
+            bail!("missing shell command");
+        }
+        if filter {
+            self.check_writable()?;
+        }
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let (start, end) = match self.hex_view.selection()? {
+                    Some((start, end)) => (start, end + 1),
+                    None => self.hex_view.visible_range()?,
+                };
+                let bytes = self.hex_view.document().read_range(start, (end - start) as usize)?;
+
+                let output = match util::popen("sh", &["-c", shell_command], bytes) {
+                    Ok(o) => o,
+                    Err(code) => bail!("{}: failed to run the command (error {})", shell_command, code),
+                };
+                if !output.status.success() {
+                    match output.status.code() {
+                        Some(code) => bail!("{}: shell returned {}", shell_command, code),
+                        None => bail!("{}: terminated by a signal", shell_command),
+                    }
+                }
+
+                if filter {
+                    if output.stdout.len() as u64 != end - start {
+                        self.check_resizable()?;
+                    }
+                    self.hex_view.replace_range(start, end, &output.stdout)?;
+                    if self.hex_view.selection()?.is_some() {
+                        self.cancel_visual()?;
+                    }
+                    self.show_message(&format!("{} bytes filtered into {} bytes", end - start, output.stdout.len()));
+                } else {
+                    let text = String::from_utf8_lossy(&output.stdout);
+                    self.show_message(text.lines().next().unwrap_or(""));
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Moves the cursor to an absolute offset, clamping it to the last byte of the file.
+    pub fn goto(&mut self, offset: u64) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let len = self.hex_view.document().len()?;
+                let clamped = offset.min(len.saturating_sub(1));
+
+                self.seek(clamped as i64)?;
+
+                if clamped != offset {
+                    self.show_message(&format!("{:#x}: past the end of the file, moved to {:#x}", offset, clamped));
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Moves the cursor to the start of the line at a percentage of the file size.
+    pub fn goto_percent(&mut self, percent: u64) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let len = self.hex_view.document().len()?;
+                let offset = (len as u128 * percent as u128 / 100) as u64;
+                let offset = offset.min(len.saturating_sub(1));
+                let bytes_per_line = self.config.bytes_per_line as u64;
+
+                self.goto(offset - offset % bytes_per_line)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Reads an offset of `width` bytes at the cursor and goes to it.
+    pub fn follow(&mut self, width: usize, little_endian: bool) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let offset = self.hex_view.read_value_at_cursor(width, little_endian)?;
+                self.goto(offset)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Invokes the search prompt and searches for the entered pattern.
+    /// The pattern is either a hex string, where `??` matches any byte, or a literal string if it
+    /// starts with `"`. The view follows the first match of the pattern as it is typed, and
+    /// returns to where it was if the search is cancelled.
+    pub fn search(&mut self)
+    {
+        let (seek, cursor, highlight, search_pattern) = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let position = self.hex_view.get_seek().and_then(|seek| {
+                    Ok((seek, self.hex_view.cursor_offset()?))
+                });
+                match position {
+                    Ok((seek, cursor)) => {
+                        (seek, cursor, self.hex_view.highlight(), self.hex_view.search_pattern())
+                    },
+                    Err(e) => return self.show_message(&e.to_string()),
+                }
+            },
+        };
+
+        let pattern = self.prompt_with('/', |_| true, |editor, pattern| {
+            editor.preview_search(pattern, seek, cursor);
+        });
+
+        // The search starts from where the view was, whether it is cancelled or not.
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_highlight(highlight);
+                self.hex_view.set_search_pattern(search_pattern);
+                if let Err(e) = self.hex_view.set_view(seek, cursor) {
+                    self.show_message(&e.to_string());
+                }
+                self.draw_cmdline();
+            },
+        }
+
+        let pattern = match pattern {
+            Some(p) => p,
+            None => return,
+        };
+
+        match util::parse_pattern(&pattern) {
+            Ok(needle) => {
+                self.last_search = Some(needle);
+                self.search_next(true);
+            },
+            Err(e) => self.show_message(&e.to_string()),
+        }
+    }
+
+    /// Moves the view to the first match of a pattern that is being typed, after the cursor the
+    /// search started at, or back to where it was if there is no match. Incomplete patterns are
+    /// ignored.
+    fn preview_search(&mut self, pattern: &str, seek: u64, cursor: u64)
+    {
+        let needle = match util::parse_pattern(pattern) {
+            Ok(n) => n,
+            Err(_) if !pattern.is_empty() => return,
+            Err(_) => Vec::new(),
+        };
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let found = if needle.is_empty() {
+                    None
+                } else {
+                    self.hex_view.document().search(&needle, cursor + 1)
+                };
+
+                let result = match found {
+                    Some(offset) => {
+                        self.hex_view.set_highlight(Some((offset, needle.len() as u64)));
+                        self.hex_view.set_search_pattern(Some(needle));
+                        self.hex_view.set_cursor_offset(offset).map(|_| ())
+                    },
+                    None => {
+                        self.hex_view.set_highlight(None);
+                        self.hex_view.set_search_pattern(None);
+                        self.hex_view.set_view(seek, cursor)
+                    },
+                };
+                if result.is_ok() {
+                    self.hex_view.refresh();
+                }
+            },
+        }
+    }
+
+    /// Jumps to the next match of the last search, either forward or backward.
+    pub fn search_next(&mut self, forward: bool)
+    {
+        let needle = match &self.last_search {
+            Some(n) => n.clone(),
+            None => {
+                self.show_message("no previous search pattern");
+                return;
+            },
+        };
+
+        let cursor = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => match self.hex_view.cursor_offset() {
+                Ok(c) => c,
+                Err(e) => return self.show_message(&e.to_string()),
+            },
+        };
+
+        if forward {
+            self.jump_to_match(&needle, cursor + 1, true);
+        } else {
+            self.jump_to_match(&needle, cursor, false);
+        }
+    }
+
+    /// Removes the highlighting of the search matches, until the next search.
+    pub fn clear_search_highlight(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_search_pattern(None);
+                self.hex_view.set_highlight(None);
+                self.hex_view.draw()?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Jumps to the first match at or after an offset, or the last match before it if not forward,
+    /// wrapping around. All the matches on the screen are highlighted.
+    fn jump_to_match(&mut self, needle: &[Option<u8>], from: u64, forward: bool)
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_search_pattern(Some(needle.to_vec()));
+
+                let cursor = match self.hex_view.cursor_offset() {
+                    Ok(c) => c,
+                    Err(e) => return self.show_message(&e.to_string()),
+                };
+
+                let found = if forward {
+                    self.hex_view.document().search(needle, from)
+                } else {
+                    self.hex_view.document().search_backward(needle, from)
+                };
+
+                match found {
+                    Some(offset) => {
+                        self.hex_view.set_highlight(Some((offset, needle.len() as u64)));
+                        match self.hex_view.set_cursor_offset(offset) {
+                            Ok(seek) => {
+                                self.seek = seek;
+                                self.push_jump(cursor);
+                            },
+                            Err(e) => self.show_message(&e.to_string()),
+                        }
+                        let (y, x) = self.hex_view.get_cur_yx();
+                        self.win.mv(y, x);
+                    },
+                    None => self.show_message("pattern not found"),
+                }
+            },
+        }
+    }
+
+    /// Moves the cursor count runs of equal bytes forward, or backward if not forward.
+    pub fn move_over_runs(&mut self, forward: bool, count: u32) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let mut offset = self.hex_view.cursor_offset()?;
+                for _ in 0..count {
+                    offset = if forward {
+                        self.hex_view.document().next_run_boundary(offset)?
+                    } else {
+                        self.hex_view.document().prev_run_boundary(offset)?
+                    };
+                }
+                self.seek = self.hex_view.set_cursor_offset(offset)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Moves the cursor to the count-th next or previous non-zero byte.
+    pub fn next_nonzero(&mut self, forward: bool, count: u32) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let mut offset = self.hex_view.cursor_offset()?;
+                for _ in 0..count {
+                    let next = if forward {
+                        self.hex_view.document().next_nonzero(offset)?
+                    } else {
+                        self.hex_view.document().prev_nonzero(offset)?
+                    };
+                    offset = match next {
+                        Some(o) => o,
+                        None => bail!("no more non-zero bytes"),
+                    };
+                }
+                self.seek = self.hex_view.set_cursor_offset(offset)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the editor can quit. Unless forced, it can't with unsaved changes.
+    /// Returns true if the editor should quit.
+    pub fn quit(&mut self, force: bool) -> anyhow::Result<bool>
+    {
+        let dirty = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => self.hex_view.is_dirty(),
+        };
+        if dirty && !force {
+            bail!("E37: No write since last change (add ! to override)");
+        }
+
+        let hidden_dirty = self.buffers.iter()
+            .flatten()
+            .find(|buffer| buffer.hex_view.is_dirty());
+        if let (Some(buffer), false) = (hidden_dirty, force) {
+            bail!("E162: No write since last change for buffer \"{}\"", buffer.config.infile_name.display());
+        }
+
+        Ok(true)
+    }
+
+    /// Writes the file to the disk.
+    /// If forced, a read-only file is reopened as read-write first.
+    pub fn write(&mut self, force: bool) -> anyhow::Result<()>
+    {
+        if self.config.ro {
+            if !force {
+                bail!("E45: 'readonly' option is set (add ! to override)");
+            }
+            if self.config.output.is_none() {
+                self.check_written_back()?;
+                self.hex_view.reopen(&self.config.infile_name)?;
+            }
+            self.config.ro = false;
+        }
+
+        if let Some(output) = self.config.output.clone() {
+            return self.write_output(&output);
+        }
+
+        let written = self.hex_view.flush()?;
+        self.remove_swap();
+        self.hex_view.draw()?;
+        self.show_message(&format!(
+            "\"{}\" {}B written",
+            self.config.infile_name.to_string_lossy(),
+            written
+        ));
+
+        Ok(())
+    }
+
+    /// Writes the file, with the pending edits, to a new file, and switches to editing that file.
+    /// An existing file is only overwritten if forced.
+    pub fn save_as(&mut self, path: &Path, force: bool) -> anyhow::Result<()>
+    {
+        if path.exists() && !force {
+            bail!("E13: File exists (add ! to override)");
+        }
+
+        // The file can be the one being edited, which is read until it's replaced.
+        let len = self.hex_view.document().len()?;
+        let document = self.hex_view.document_mut();
+        let (file, written) = util::write_new_file(path, force, |out| document.write_range(0, len, out))?;
+
+        // The old file keeps its contents, and the edits are in the new one.
+        self.remove_swap();
+        self.hex_view.switch_file(file, path)?;
+        self.config.infile_name = path.to_path_buf();
+        self.config.from_stdin = false;
+        self.config.temporary = false;
+        self.config.output = None;
+        self.config.ro = false;
+
+        self.hex_view.draw()?;
+        self.show_message(&format!("\"{}\" {}B written", path.display(), written));
+
+        Ok(())
+    }
+
+    /// Writes the file, with the pending edits, to the output file. The file being edited is left
+    /// as it is, with the edits pending.
+    fn write_output(&mut self, path: &Path) -> anyhow::Result<()>
+    {
+        self.check_destination(path)?;
+
+        let len = self.hex_view.document().len()?;
+        let document = self.hex_view.document_mut();
+        let (_, written) = util::write_new_file(path, true, |out| document.write_range(0, len, out))?;
+        self.hex_view.set_saved();
+
+        self.hex_view.draw()?;
+        self.show_message(&format!("\"{}\" {}B written", path.display(), written));
+
+        Ok(())
+    }
+
+    /// Fails if writing to the path would overwrite the file being edited, or the file it is a
+    /// temporary copy of.
+    fn check_destination(&self, path: &Path) -> anyhow::Result<()>
+    {
+        let mut files = vec![self.hex_view.document().file.metadata()?];
+        if !self.config.from_stdin {
+            if let Ok(meta) = std::fs::metadata(&self.config.infile_name) {
+                files.push(meta);
+            }
+        }
+        if files.iter().any(|meta| util::names_file(path, meta)) {
+            bail!("{}: is the file being edited", path.display());
+        }
+
+        Ok(())
+    }
+
+    /// Displays a message in the command line, in place of the mode indicator, until the next key
+    /// is pressed.
+    pub fn show_message(&mut self, message: &str)
+    {
+        self.message = Some(message.to_string());
+        self.draw_cmdline();
+    }
+
+    /// Draws the message, or the mode indicator if there is no message, in the command line.
+    fn draw_cmdline(&self)
+    {
+        self.cmdline_win.clear();
+        self.cmdline_win.mv(0, 0);
+        match &self.message {
+            Some(message) => self.cmdline_win.printw(message),
+            None => self.cmdline_win.printw(self.mode_indicator()),
+        };
+    }
+
+    /// Returns the indicator of the current mode.
+    fn mode_indicator(&self) -> &'static str
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => self.hex_view.mode_indicator(),
+        }
+    }
+
+    /// Invokes the command prompt, listens for keys, and returns teh input.
+    pub fn prompt(&mut self, prefix: char) -> Option<String>
+    {
+        self.prompt_with(prefix, |_| true, |_, _| ())
+    }
+
+    /// Invokes a prompt, like `prompt`, and calls `on_change` with the text whenever it changes.
+    /// Typed characters are rejected if `accept` returns false for the resulting text.
+    fn prompt_with<F>(&mut self, prefix: char, accept: fn(&str) -> bool, mut on_change: F) -> Option<String>
+        where F: FnMut(&mut Self, &str)
+    {
+        let (y, x) = self.win.get_cur_yx();
+        let mut command = String::new();
+
+        self.cmdline_win.clear();
+        self.win.mv(self.cmdline_win.get_beg_y(), self.cmdline_win.get_beg_x() + 1);
+        self.cmdline_win.addch(prefix);
+        self.cmdline_win.refresh();
+
+        loop {
+            match self.win.getch() {
+                Some(pancurses::Input::Character(c)) => {
+                    if c == '\n' {
+                        break;
+                    } else if c == 0x1B as char {
+                        command.clear();
+                        break;
+                    } else if !accept(&format!("{}{}", command, c)) {
+                        pancurses::beep();
+                    } else {
+                        self.cmdline_win.addch(c);
+                        self.cmdline_win.refresh();
+                        command.push(c);
+                        self.prompt_changed(&command, &mut on_change);
+                    }
+                },
+                Some(pancurses::Input::KeyBackspace) => {
+                    if command.is_empty() {
+                        break;
+                    }
+
+                    let (cur_y, cur_x) = self.cmdline_win.get_cur_yx();
+                    self.cmdline_win.mv(cur_y, cur_x - 1);
+                    self.cmdline_win.delch();
+                    self.cmdline_win.refresh();
+                    command.pop();
+                    self.prompt_changed(&command, &mut on_change);
+                }
+                Some(_) => (),
+                None => ()
+            }
+        }
+
+        self.cmdline_win.clear();
+        self.cmdline_win.printw(self.mode_indicator());
+        self.win.mv(y, x);
+
+        if !command.is_empty() {
+            Some(command)
+        } else {
+            None
+        }
+    }
+
+    /// Calls the callback of a prompt and moves the cursor back to the end of the prompt.
+    fn prompt_changed<F>(&mut self, command: &str, on_change: &mut F)
+        where F: FnMut(&mut Self, &str)
+    {
+        on_change(self, command);
+
+        let (cur_y, cur_x) = self.cmdline_win.get_cur_yx();
+        self.win.mv(self.cmdline_win.get_beg_y() + cur_y, self.cmdline_win.get_beg_x() + cur_x);
+        self.win.refresh();
+    }
+
+    /// Draw the screen.
+    fn draw(&mut self)
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.draw().ok();
+            }
+        }
+
+        self.draw_cmdline();
+    }
+
+    /// Refresh the screen.
+    pub fn refresh(&self)
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.refresh();
+                // The cursor of the main window needs to be set on every refresh, for some reason.
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+
+            },
+        }
+
+        self.cmdline_win.refresh();
+    }
+
+    /// Block until the terminal is large enough for the hex view.
+    /// Exit, if the user presses 'q'.
+    fn wait_for_size(win: &Window, min_width: i32)
+    {
+        while win.get_max_x() < min_width || win.get_max_y() < 2 {
+            win.erase();
+            win.mvprintw(0, 0, "terminal too small");
+            win.refresh();
+
+            match win.getch() {
+                Some(pancurses::Input::KeyResize) => {
+                    pancurses::resize_term(0, 0);
+                },
+                Some(pancurses::Input::Character('q')) => {
+                    pancurses::endwin();
+                    std::process::exit(0);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    /// Handle a resize of the terminal.
+    pub fn resize(&mut self)
+    {
+        pancurses::resize_term(0, 0);
+
+        self.win.erase();
+
+        if self.win.get_max_x() < self.hex_view.required_width()
+            || self.win.get_max_y() < 2 {
+            self.win.mvprintw(0, 0, "terminal too small");
+            self.win.refresh();
+            return;
+        }
+
+        let (y, x) = self.win.get_max_yx();
+        self.cmdline_win = self.win.derwin(1, x, y - 1, 0)
+            .expect("failed to create a subwin");
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let win = self.win.derwin(y - 1, x, 0, 0)
+                    .expect("failed to create a subwin");
+                if let Ok(seek) = self.hex_view.resize(win) {
+                    self.seek = seek;
+                }
+            },
+        }
+
+        self.draw();
+        self.refresh();
+    }
+
+    /// Finish
+    pub fn end(&mut self)
+    {
+        for index in 0..self.buffers.len() {
+            if index != self.current {
+                self.swap_buffer(index);
+                self.remove_swap();
+            }
+        }
+        self.remove_swap();
+        pancurses::endwin();
+    }
+
+    /// Return the width of the entire screen.
+    pub fn width(&self) -> i32
+    {
+        self.win.get_max_x()
+    }
+
+    /// Return the width of the entire screen.
+    pub fn height(&self) -> i32
+    {
+        self.win.get_max_y()
+    }
+
+    /// Handles a key press: accumulates the count, completes a two-key command, or runs the action
+    /// the key is mapped to. Returns true if the editor should quit.
+    pub fn handle_key(&mut self, input: Input) -> bool
+    {
+        // Complete a two-key command. An unknown second key cancels it.
+        if let Some((prefix, n)) = self.pending.take() {
+            let result = match (prefix, input) {
+                (Action::GotoPrefix, Input::Character('g')) => self.seek(0),
+                (Action::GotoPrefix, Input::Character('R')) => self.replace_string(),
+                (Action::GotoPrefix, Input::Character('o')) => self.goto_prompt(),
+                (Action::NextPrefix, Input::Character('d')) => self.next_diff(true, n),
+                (Action::PrevPrefix, Input::Character('d')) => self.next_diff(false, n),
+                (Action::NextPrefix, Input::Character('z')) => self.next_nonzero(true, n),
+                (Action::PrevPrefix, Input::Character('z')) => self.next_nonzero(false, n),
+                (Action::RegisterPrefix, Input::Character(c @ '0'..='9')) => {
+                    self.selected_register = Some(c as usize - '0' as usize);
+                    Ok(())
+                },
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                self.show_message(&e.to_string());
+            }
+            self.count = 0;
+            self.refresh();
+            return false;
+        }
+
+        // Accumulate the count. A leading 0 is a command on its own.
+        if let Input::Character(c) = input {
+            if let Some(digit) = c.to_digit(10) {
+                if digit != 0 || self.count != 0 {
+                    self.count = self.count.saturating_mul(10).saturating_add(digit);
+                    return false;
+                }
+            }
+        }
+        let n = self.count.max(1);
+        self.count = 0;
+
+        let action = match input {
+            Input::Character(c) => self.config.keymap.get(&c).copied(),
+            Input::KeyResize => {
+                self.resize();
+                None
+            },
+            key => keymap::SPECIAL_KEYS.iter().find(|(k, _, _)| *k == key).map(|(_, _, a)| *a),
+        };
+
+        let result = match action {
+            Some(action @ (Action::Quit | Action::ForceQuit)) => match self.quit(action == Action::ForceQuit) {
+                Ok(true) => return true,
+                result => result.map(|_| ()),
+            },
+            Some(Action::Left) => {
+                self.move_cursor(Direction::Left, n.min(i32::MAX as u32) as i32);
+                Ok(())
+            },
+            Some(Action::Down) => {
+                self.move_cursor(Direction::Down, n.min(i32::MAX as u32) as i32);
+                Ok(())
+            },
+            Some(Action::Up) => {
+                self.move_cursor(Direction::Up, n.min(i32::MAX as u32) as i32);
+                Ok(())
+            },
+            Some(Action::Right) => {
+                self.move_cursor(Direction::Right, n.min(i32::MAX as u32) as i32);
+                Ok(())
+            },
+            Some(Action::LineStart) => self.move_to_line_start(),
+            Some(Action::LineEnd) => self.move_to_line_end(),
+            Some(Action::NextRun) => self.move_over_runs(true, n),
+            Some(Action::PrevRun) => self.move_over_runs(false, n),
+            Some(Action::Top) => self.seek(0),
+            Some(Action::Bottom) => self.seek(-1),
+            Some(prefix @ (Action::GotoPrefix | Action::NextPrefix | Action::PrevPrefix | Action::RegisterPrefix)) => {
+                self.pending = Some((prefix, n));
+                Ok(())
+            },
+            Some(Action::Undo) => self.undo(),
+            Some(Action::Redo) => self.redo(),
+            Some(Action::ScrollUp) => self.scroll(Direction::Up, n),
+            Some(Action::ScrollDown) => self.scroll(Direction::Down, n),
+            Some(Action::PageUp) => {
+                self.scroll_page(Direction::Up, n);
+                Ok(())
+            },
+            Some(Action::PageDown) => {
+                self.scroll_page(Direction::Down, n);
+                Ok(())
+            },
+            Some(Action::JumpBack) => self.jump_back(),
+            Some(Action::JumpForward) => self.jump_forward(),
+            Some(Action::Mark) => self.mark(),
+            Some(Action::JumpToMark) => self.jump_to_mark(),
+            Some(Action::SwitchView) => {
+                self.switch_view();
+                Ok(())
+            },
+            Some(Action::SwitchPane) => {
+                self.switch_pane();
+                Ok(())
+            },
+            Some(Action::Replace) => self.replace().map(|_| ()),
+            Some(Action::ReplaceMany) => self.replace_many(),
+            Some(Action::Insert) => self.insert_many(),
+            Some(Action::Delete) => self.delete(n as u64),
+            Some(Action::Repeat) => self.repeat(),
+            Some(Action::Inspector) => self.toggle_inspector(),
+            Some(Action::Visual) => self.toggle_visual(),
+            Some(Action::Yank) => self.yank(),
+            Some(Action::Paste) => self.paste(false),
+            Some(Action::PasteInsert) => self.paste(true),
+            Some(Action::Cancel) => self.cancel_visual(),
+            Some(Action::Command) => {
+                if self.command() {
+                    return true;
+                }
+                Ok(())
+            },
+            Some(Action::Search) => {
+                self.search();
+                Ok(())
+            },
+            Some(Action::SearchNext) => {
+                self.search_next(true);
+                Ok(())
+            },
+            Some(Action::SearchPrev) => {
+                self.search_next(false);
+                Ok(())
+            },
+            Some(Action::Help) => {
+                self.help();
+                Ok(())
+            },
+            Some(Action::Nop) | None => Ok(()),
+        };
+        if let Err(e) = result {
+            self.show_message(&e.to_string());
+        }
+        // A selected register is only used by the next command.
+        if action != Some(Action::RegisterPrefix) {
+            self.selected_register = None;
+        }
+        self.update_swap();
+        self.refresh();
+
+        false
+    }
+
+    /// Listen for an input event.
+    /// A pending message is cleared by the key press.
+    pub fn getch(&mut self) -> Option<pancurses::Input>
+    {
+        let key = self.win.getch();
+
+        if self.message.take().is_some() {
+            self.draw_cmdline();
+        }
+
+        key
+    }
+
+    /// Returns true if Esc has been pressed, without waiting for a key, so that long operations
+    /// can be cancelled between their chunks. Any other key is kept for later.
+    pub fn check_cancel(&self) -> bool
+    {
+        self.win.nodelay(true);
+        let key = self.win.getch();
+        self.win.nodelay(false);
+
+        match key {
+            Some(Input::Character('\x1b')) => true,
+            Some(key) => {
+                self.win.ungetch(&key);
+                false
+            },
+            None => false,
+        }
+    }
+
+    /// Move the cursor.
+    pub fn move_cursor(&mut self, direction: Direction, count: i32)
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                if let Err(e) = self.hex_view.move_cursor(direction, count) {
+                    self.show_message(&e.to_string());
+                }
+                // The view could have scrolled before the move failed.
+                if let Ok(seek) = self.hex_view.get_seek() {
+                    self.seek = seek;
+                }
+                // Again, the cursor of the main window needs to be reset.
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            }
+        }
+    }
+
+    /// Moves the cursor to the beginning of the line.
+    pub fn move_to_line_start(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.move_to_line_start()?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Moves the cursor to the end of the line.
+    pub fn move_to_line_end(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.move_to_line_end()?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Seek - jump to a line-aligned offset, advancing the cursor properly.
+    /// Accepts both positive and negative values - if negative, start from the end.
+    pub fn seek(&mut self, offset: i64) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let cursor = self.hex_view.cursor_offset()?;
+                self.seek = self.hex_view.seek(offset)?;
+                self.push_jump(cursor);
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compares the file against another file, and jumps to the first difference.
+    /// The tail of the longer file counts as different.
+    pub fn diff(&mut self, path: &Path) -> anyhow::Result<()>
+    {
+        let mut other = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => bail!("{}: {}", path.display(), e),
+        };
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let diffs = self.hex_view.document().diff(&mut other, || self.check_cancel())?;
+
+                let bytes: u64 = diffs.iter().map(|(_, len)| len).sum();
+                self.diffs = diffs;
+
+                if self.diffs.is_empty() {
+                    self.hex_view.set_highlight(None);
+                    self.show_message("no differences");
+                } else {
+                    let (start, _) = self.diffs[0];
+                    self.jump_to_diff(start)?;
+                    self.show_message(&format!("{} differences ({}B)", self.diffs.len(), bytes));
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Moves to the `count`th next difference, or the previous one if not forward.
+    pub fn next_diff(&mut self, forward: bool, count: u32) -> anyhow::Result<()>
+    {
+        if self.diffs.is_empty() {
+            bail!("no differences (use :diff PATH)");
+        }
+
+        let cursor = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => self.hex_view.cursor_offset()?,
+        };
+        let index = if forward {
+            self.diffs.iter().position(|(start, _)| *start > cursor)
+                .map(|i| i + count as usize - 1)
+        } else {
+            self.diffs.iter().rposition(|(start, _)| *start < cursor)
+                .and_then(|i| i.checked_sub(count as usize - 1))
+        };
+        let (start, _) = match index.and_then(|i| self.diffs.get(i)) {
+            Some(d) => *d,
+            None => bail!("no more differences"),
+        };
+
+        self.jump_to_diff(start)?;
+        let index = self.diffs.iter().position(|(s, _)| *s == start).unwrap_or(0);
+        self.show_message(&format!("difference {} of {}", index + 1, self.diffs.len()));
+
+        Ok(())
+    }
+
+    /// Highlights the difference starting at an offset and moves the cursor to it.
+    fn jump_to_diff(&mut self, start: u64) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let cursor = self.hex_view.cursor_offset()?;
+                let len = self.diffs.iter().find(|(s, _)| *s == start).map_or(1, |(_, len)| *len);
+                self.hex_view.set_highlight(Some((start, len)));
+                self.seek = self.hex_view.set_cursor_offset(start)?;
+                self.push_jump(cursor);
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Records the offset the cursor has jumped from in the jumplist.
+    /// Newer jumps, that were moved back from, are discarded.
+    fn push_jump(&mut self, offset: u64)
+    {
+        self.jumplist.truncate(self.jump_index);
+        self.append_jump(offset);
+        self.jump_index = self.jumplist.len();
+        self.update_jump_position();
+    }
+
+    /// Adds an offset to the end of the jumplist, dropping the oldest one if there are more than
+    /// JUMPLIST_SIZE. The index keeps pointing at the same position.
+    fn append_jump(&mut self, offset: u64)
+    {
+        self.jumplist.push(offset);
+        if self.jumplist.len() > JUMPLIST_SIZE {
+            self.jumplist.remove(0);
+            self.jump_index = self.jump_index.saturating_sub(1);
+        }
+    }
+
+    /// Shows the position in the jumplist in the status line. Past the newest jump, the cursor
+    /// counts as one more position.
+    fn update_jump_position(&mut self)
+    {
+        let position = match (self.jumplist.len(), self.jump_index) {
+            (0, _) => None,
+            (len, index) if index == len => Some((len + 1, len + 1)),
+            (len, index) => Some((index + 1, len)),
+        };
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_jump_position(position).ok();
+            },
+        }
+    }
+
+    /// Moves back to the previous position in the jumplist.
+    pub fn jump_back(&mut self) -> anyhow::Result<()>
+    {
+        if self.jump_index == 0 {
+            bail!("already at the oldest jump");
+        }
+
+        // Remember the current position, so it can be jumped forward to.
+        if self.jump_index == self.jumplist.len() {
+            let cursor = match self.cur_view {
+                ViewType::Hex | ViewType::Bits => self.hex_view.cursor_offset()?,
+            };
+            self.append_jump(cursor);
+        }
+
+        self.jump_to_index(self.jump_index - 1)
+    }
+
+    /// Moves forward to the next position in the jumplist.
+    pub fn jump_forward(&mut self) -> anyhow::Result<()>
+    {
+        if self.jump_index + 1 >= self.jumplist.len() {
+            bail!("already at the newest jump");
+        }
+
+        self.jump_to_index(self.jump_index + 1)
+    }
+
+    /// Reads the name of a mark. Returns None if Esc is pressed.
+    fn read_mark_name(&self) -> anyhow::Result<Option<char>>
+    {
+        match self.win.getch() {
+            Some(pancurses::Input::Character('\u{1b}')) => Ok(None),
+            Some(pancurses::Input::Character(c)) if c.is_ascii_alphabetic() => Ok(Some(c)),
+            Some(pancurses::Input::Character(c)) => bail!("{}: invalid mark name", c),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads a mark name and sets the mark at the cursor.
+    pub fn mark(&mut self) -> anyhow::Result<()>
+    {
+        let name = match self.read_mark_name()? {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+
+        let cursor = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => self.hex_view.cursor_offset()?,
+        };
+        self.set_mark(name, cursor);
+
+        Ok(())
+    }
+
+    /// Sets the mark `name` at `offset`.
+    pub fn set_mark(&mut self, name: char, offset: u64)
+    {
+        self.marks.insert(name, offset);
+    }
+
+    /// Returns all the marks.
+    pub fn marks(&self) -> &HashMap<char, u64>
+    {
+        &self.marks
+    }
+
+    /// Reads a mark name and jumps to the mark.
+    pub fn jump_to_mark(&mut self) -> anyhow::Result<()>
+    {
+        let name = match self.read_mark_name()? {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+
+        let offset = match self.marks.get(&name) {
+            Some(o) => *o,
+            None => bail!("{}: mark not set", name),
+        };
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let cursor = self.hex_view.cursor_offset()?;
+                // The file could have shrunk since the mark was set.
+                let len = self.hex_view.document().len()?;
+                self.seek = self.hex_view.set_cursor_offset(offset.min(len.saturating_sub(1)))?;
+                self.push_jump(cursor);
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Lists all the marks in an overlay.
+    fn list_marks(&mut self) -> anyhow::Result<()>
+    {
+        if self.marks.is_empty() {
+            bail!("no marks set");
+        }
+
+        let mut marks: Vec<(&char, &u64)> = self.marks.iter().collect();
+        marks.sort();
+
+        let mut lines = vec![String::from("mark  offset")];
+        for (name, offset) in marks {
+            lines.push(format!(" {}    {:#010x}", name, offset));
+        }
+
+        self.show_overlay(&lines);
+
+        Ok(())
+    }
+
+    /// Opens another file in the buffer list, without switching to it.
+    pub fn add_buffer(&mut self, file: File, config: Config)
+    {
+        let (y, x) = self.win.get_max_yx();
+        let mut hex_view = HexView::new(
+            self.win.derwin(y - 1, x, 0, 0)
+                .expect("failed to create a subwin"),
+            file,
+            &config
+        );
+        if pancurses::has_colors() {
+            hex_view.init_colors();
+        }
+
+        self.buffers.push(Some(Buffer {
+            hex_view,
+            seek: 0,
+            config,
+            jumplist: Vec::new(),
+            jump_index: 0,
+            marks: HashMap::new(),
+            diffs: Vec::new(),
+            swap_keys: None
+        }));
+
+        // Offer to recover its swap file, showing the file while asking.
+        let current = self.current;
+        self.swap_buffer(self.buffers.len() - 1);
+        self.hex_view.draw().ok();
+        self.recover_swap();
+        self.swap_buffer(current);
+        self.draw();
+    }
+
+    /// Switches to a file in the buffer list, or opens it as a new buffer if it isn't open.
+    pub fn edit(&mut self, path: &Path) -> anyhow::Result<()>
+    {
+        let same_file = |other: &Path| match (std::fs::canonicalize(path), std::fs::canonicalize(other)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => path == other,
+        };
+        let open = self.buffers.iter().position(|buffer| match buffer {
+            Some(b) => !b.config.temporary && same_file(&b.config.infile_name),
+            None => !self.config.temporary && same_file(&self.config.infile_name),
+        });
+        if let Some(index) = open {
+            self.switch_buffer(index);
+            return Ok(());
+        }
+
+        // The file is opened with the current settings.
+        let mut config = self.config.clone();
+        config.infile_name = path.to_path_buf();
+        config.other_files.clear();
+        config.output = None;
+        config.from_stdin = false;
+        config.temporary = false;
+        config.ro = false;
+        let file = open_input(&mut config)?;
+
+        self.add_buffer(file, config);
+        self.switch_buffer(self.buffers.len() - 1);
+
+        Ok(())
+    }
+
+    /// Exchanges the state of the current file with the state of the file at the index in the
+    /// buffer list, which becomes the current one.
+    fn swap_buffer(&mut self, index: usize)
+    {
+        let mut buffer = self.buffers[index].take().expect("the buffer is the current one");
+
+        std::mem::swap(&mut self.hex_view, &mut buffer.hex_view);
+        std::mem::swap(&mut self.seek, &mut buffer.seek);
+        std::mem::swap(&mut self.config, &mut buffer.config);
+        std::mem::swap(&mut self.jumplist, &mut buffer.jumplist);
+        std::mem::swap(&mut self.jump_index, &mut buffer.jump_index);
+        std::mem::swap(&mut self.marks, &mut buffer.marks);
+        std::mem::swap(&mut self.diffs, &mut buffer.diffs);
+        std::mem::swap(&mut self.swap_keys, &mut buffer.swap_keys);
+
+        self.buffers[self.current] = Some(buffer);
+        self.current = index;
+    }
+
+    /// Switches to the file at the index in the buffer list, where it was left, and shows its
+    /// position in the list.
+    pub fn switch_buffer(&mut self, index: usize)
+    {
+        if index != self.current {
+            self.swap_buffer(index);
+            // The terminal could have been resized since the file was shown.
+            self.resize();
+        }
+
+        self.show_message(&format!(
+            "[{}/{}] \"{}\"",
+            index + 1,
+            self.buffers.len(),
+            self.config.infile_name.display()
+        ));
+    }
+
+    /// Lists the open files in an overlay. The current one is marked with `%`, and the ones with
+    /// unsaved changes with `+`.
+    fn list_buffers(&mut self)
+    {
+        let mut lines = vec![String::from("buf    name")];
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            let (name, dirty) = match buffer {
+                Some(b) => (b.config.infile_name.display(), b.hex_view.is_dirty()),
+                None => (self.config.infile_name.display(), self.hex_view.is_dirty()),
+            };
+            let current = if buffer.is_none() { '%' } else { ' ' };
+            let dirty = if dirty { '+' } else { ' ' };
+            lines.push(format!("{:>3} {}{} \"{}\"", index + 1, current, dirty, name));
+        }
+
+        self.show_overlay(&lines);
+    }
+
+    /// Shows the key mappings, generated from the keymap, and the commands in an overlay.
+    fn help(&mut self)
+    {
+        let mut lines = vec![String::from("KEYS"), String::new()];
+        for &action in keymap::ACTIONS {
+            let mut keys: Vec<String> = self.config.keymap.iter()
+                .filter(|(_, a)| **a == action)
+                .map(|(c, _)| keymap::key_name(*c))
+                .collect();
+            keys.sort();
+            keys.extend(keymap::SPECIAL_KEYS.iter()
+                .filter(|(_, _, a)| *a == action)
+                .map(|(_, name, _)| name.to_string()));
+            if keys.is_empty() {
+                continue;
+            }
+            lines.push(format!("  {:<16} {:<14} {}", keys.join(" "), action.name(), action.description()));
+        }
+
+        lines.push(String::new());
+        lines.push(String::from("COMMANDS"));
+        lines.push(String::new());
+        for (usage, description) in COMMANDS {
+            lines.push(format!("  :{:<24} {}", usage, description));
+        }
+
+        self.show_overlay(&lines);
+    }
+
+    /// Shows the lines in an overlay over the view, a page at a time. Any key shows the next page,
+    /// and `q` or ESC dismisses the overlay.
+    fn show_overlay(&mut self, lines: &[String])
+    {
+        let (y, x) = self.win.get_max_yx();
+        let height = (lines.len() as i32 + 1).min(y - 1);
+        let overlay = match self.win.derwin(height, x, 0, 0) {
+            Ok(w) => w,
+            Err(_) => return,
+        };
+
+        let page_size = height as usize - 1;
+        for (page, chunk) in lines.chunks(page_size).enumerate() {
+            overlay.erase();
+            for (i, line) in chunk.iter().enumerate() {
+                overlay.mvaddnstr(i as i32, 0, line, x);
+            }
+            let last = (page + 1) * page_size >= lines.len();
+            let prompt = if last { "Press any key to continue" } else { "-- More -- (q to quit)" };
+            overlay.mvprintw(height - 1, 0, prompt);
+            overlay.mvchgat(height - 1, 0, -1, pancurses::A_REVERSE, 0);
+            overlay.refresh();
+
+            if let Some(Input::Character('q' | '\u{1b}')) = self.win.getch() {
+                break;
+            }
+        }
+
+        // The overlay shares the memory of the main window, so it is cleared by redrawing.
+        self.win.erase();
+        self.draw();
+    }
+
+    /// Moves the cursor to the position at `index` in the jumplist.
+    fn jump_to_index(&mut self, index: usize) -> anyhow::Result<()>
+    {
+        let offset = self.jumplist[index];
+        self.jump_index = index;
+        self.update_jump_position();
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                // The file could have shrunk since the jump was recorded.
+                let len = self.hex_view.document().len()?;
+                self.seek = self.hex_view.set_cursor_offset(offset.min(len.saturating_sub(1)))?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Scrolls the view up and down.
+    pub fn scroll(&mut self, direction: Direction, count: u32) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.scroll(direction, count)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Scrolls the view up and down by whole screens.
+    pub fn scroll_page(&mut self, direction: Direction, count: u32)
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                if let Ok(seek) = self.hex_view.scroll_page(direction, count) {
+                    self.seek = seek;
+                }
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+    }
+
+    /// Undoes the last change.
+    pub fn undo(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.undo()?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Redoes the last undone change.
+    pub fn redo(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.seek = self.hex_view.redo()?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Starts a visual selection at the cursor, or cancels it if there is one already.
+    pub fn toggle_visual(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                if self.hex_view.selection()?.is_some() {
+                    return self.cancel_visual();
+                }
+                self.hex_view.start_selection()?;
+            },
+        }
+
+        self.draw();
+
+        Ok(())
+    }
+
+    /// Cancels the visual selection.
+    pub fn cancel_visual(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.clear_selection()?;
+            },
+        }
+
+        self.draw();
+
+        Ok(())
+    }
+
+    /// Puts bytes into register 0, shifting the other registers up. The bytes in the last register
+    /// are dropped.
+    fn push_register(&mut self, bytes: Vec<u8>)
+    {
+        self.registers.insert(0, bytes);
+        self.registers.truncate(REGISTER_COUNT);
+    }
+
+    /// Copies the selection, or the byte under the cursor, to register 0.
+    pub fn yank(&mut self) -> anyhow::Result<()>
+    {
+        let bytes = match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let bytes = self.hex_view.yank()?;
+                if self.hex_view.selection()?.is_some() {
+                    self.cancel_visual()?;
+                }
+                bytes
+            },
+        };
+
+        self.show_message(&format!("{} bytes yanked", bytes.len()));
+        self.push_register(bytes);
+
+        Ok(())
+    }
+
+    /// Lists the registers in an overlay.
+    fn list_registers(&mut self) -> anyhow::Result<()>
+    {
+        if self.registers.is_empty() {
+            bail!("no registers set");
+        }
+
+        let mut lines = vec![String::from("reg  length  bytes")];
+        for (i, bytes) in self.registers.iter().enumerate() {
+            let shown = &bytes[..bytes.len().min(16)];
+            let more = if bytes.len() > shown.len() { "..." } else { "" };
+            lines.push(format!(" \"{}  {:<6}  {}{}", i, bytes.len(), hex::encode(shown), more));
+        }
+
+        self.show_overlay(&lines);
+
+        Ok(())
+    }
+
+    /// Pastes a register at the cursor, either overwriting the bytes or inserting them. The
+    /// register selected with `"` is pasted, or register 0.
+    pub fn paste(&mut self, insert: bool) -> anyhow::Result<()>
+    {
+        self.check_writable()?;
+        if insert {
+            self.check_resizable()?;
+        }
+
+        let index = self.selected_register.take().unwrap_or(0);
+        let register = match self.registers.get(index) {
+            Some(r) if !r.is_empty() => r.clone(),
+            _ => {
+                self.show_message("nothing to paste");
+                return Ok(());
+            },
+        };
+
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                let offset = self.hex_view.cursor_offset()?;
+
+                let pasted = if insert {
+                    self.hex_view.insert_bytes_at_offset(&register, offset)?
+                } else {
+                    // Overwrite only up to the end of the file.
+                    let len = self.hex_view.document().len()?;
+                    let count = (register.len() as u64).min(len.saturating_sub(offset)) as usize;
+                    self.hex_view.write_bytes_at_offset(&register[..count], offset)?
+                };
+                self.last_edit = Some(Edit::Paste(insert));
+
+                // Move the cursor just past the pasted bytes.
+                self.seek = self.hex_view.set_cursor_offset(offset + pasted as u64)?;
+                let (y, x) = self.hex_view.get_cur_yx();
+                self.win.mv(y, x);
+
+                self.show_message(&format!("{} bytes pasted", pasted));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Sets the character set of the canonical pane.
+    pub fn set_charset(&mut self, charset: Charset) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_charset(charset)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Sets the byte order of the multi-byte values in the inspector, and of the offsets followed
+    /// by default.
+    pub fn set_endianness(&mut self, endianness: Endianness) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_endianness(endianness)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Makes the inspector show the integers as signed, with the unsigned values shown faintly, or
+    /// the other way around.
+    pub fn set_signed(&mut self, signed: bool) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_signed(signed)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Sets how the bytes are highlighted based on their values.
+    pub fn set_highlight_scheme(&mut self, scheme: HighlightScheme) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.set_highlight_scheme(scheme)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Shows or hides the data inspector.
+    pub fn toggle_inspector(&mut self) -> anyhow::Result<()>
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.toggle_inspector()?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Switches the active pane of the current view.
+    pub fn switch_pane(&mut self)
+    {
+        match self.cur_view {
+            ViewType::Hex | ViewType::Bits => {
+                self.hex_view.switch_pane().ok();
+            },
+        }
+    }
+
+    /// Switches to the next view. The file, and the cursor offset are kept.
+    pub fn switch_view(&mut self)
+    {
+        let view = self.cur_view.next();
+
+        // The views share the hex view widget, which only changes how the bytes are shown.
+        match self.hex_view.set_byte_format(view.byte_format()) {
+            Ok(seek) => {
+                self.seek = seek;
+                self.cur_view = view;
+            },
+            Err(e) => self.show_message(&e.to_string()),
+        }
+
+        self.draw();
+        self.refresh();
+    }
+}
+
+/// Opens the file of the config, as read-write unless requested as read-only, or as read-only if
+/// that fails, and updates the config to match.
+pub fn open_input(config: &mut Config) -> anyhow::Result<File>
+{
+    // A pipe is only read, or the end of its data would never come, as the editor would hold its
+    // write end open.
+    let is_fifo = std::fs::metadata(&config.infile_name)
+        .map(|m| m.file_type().is_fifo())
+        .unwrap_or(false);
+    if is_fifo {
+        config.ro = true;
+    }
+
+    // With an output file, the file is only read.
+    let read_only = config.ro || config.output.is_some();
+
+    let file = if config.from_stdin {
+        match util::stdin_to_temp_file() {
+            // Stdin can't be written back, unless to the output file.
+            Ok(f) => {
+                config.ro |= config.output.is_none();
+                config.temporary = true;
+                f
+            },
+            Err(e) => bail!("failed to read standard input: {}", e),
+        }
+    } else {
+        let (file, ro) = util::open_file(&config.infile_name, read_only)?;
+        // The input of an output file is read-only anyway.
+        config.ro |= ro && config.output.is_none();
+        file
+    };
+
+    // Writing the output would destroy the input, which it's read from.
+    if let Some(output) = &config.output {
+        if util::names_file(output, &file.metadata()?) {
+            bail!("{}: the output is the file being edited", output.display());
+        }
+    }
+
+    // Files that can't be seeked, like pipes, are read into a temporary file, as stdin is.
+    let seekable = (&file).stream_position().is_ok();
+    if config.temporary || seekable {
+        return Ok(file);
+    }
+
+    match util::copy_to_temp_file(&mut &file) {
+        Ok(f) => {
+            // The copy can't be written back.
+            config.ro |= config.output.is_none();
+            config.temporary = true;
+            Ok(f)
+        },
+        Err(e) => bail!("{}: failed to copy the file: {}", config.infile_name.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+
+    /// Returns a path in the temporary directory that is unique to the test.
+    fn temp_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("hexvi-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn readonly_file_is_not_written()
+    {
+        let path = temp_path("readonly");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let mut config = Config { infile_name: path.clone(), ro: true, ..Config::default() };
+        let mut file = open_input(&mut config).unwrap();
+        assert!(config.ro && !config.temporary);
+        assert!(file.write_all(b"x").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn output_into_the_input_is_refused()
+    {
+        let path = temp_path("output-input");
+        let link = temp_path("output-link");
+        std::fs::write(&path, b"abc").unwrap();
+        std::fs::hard_link(&path, &link).unwrap();
+
+        for output in [&path, &link] {
+            let mut config = Config { infile_name: path.clone(), output: Some(output.clone()), ..Config::default() };
+            assert!(open_input(&mut config).is_err());
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"abc");
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&link).unwrap();
     }
 }
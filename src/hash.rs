@@ -0,0 +1,61 @@
+use std::io::Write;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// A hash function, which the bytes are written into.
+pub enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    /// Returns a new hasher for the hash function with the specified name.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name.to_ascii_lowercase().as_str() {
+            "crc32" => Some(Hasher::Crc32(crc32fast::Hasher::new())),
+            "md5" => Some(Hasher::Md5(Md5::new())),
+            "sha256" => Some(Hasher::Sha256(Sha256::new())),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the hash function.
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Hasher::Crc32(_) => "crc32",
+            Hasher::Md5(_) => "md5",
+            Hasher::Sha256(_) => "sha256",
+        }
+    }
+
+    /// Consumes the hasher and returns the hash as a hex string.
+    pub fn finish(self) -> String
+    {
+        match self {
+            Hasher::Crc32(h) => format!("{:08x}", h.finalize()),
+            Hasher::Md5(h) => hex::encode(h.finalize()),
+            Hasher::Sha256(h) => hex::encode(h.finalize()),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+    {
+        match self {
+            Hasher::Crc32(h) => h.update(buf),
+            Hasher::Md5(h) => h.update(buf),
+            Hasher::Sha256(h) => h.update(buf),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()>
+    {
+        Ok(())
+    }
+}
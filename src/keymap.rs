@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use pancurses::Input;
+
+/// Defines the Action enum, and the name, the description and the default keys of each action,
+/// and ACTIONS, from a table of `Action, "name", "description"[, "default keys"];`.
+macro_rules! actions {
+    ($($action:ident, $name:literal, $description:literal $(, $keys:literal)?;)*) => {
+        /// Actions that keys are mapped to. The default key of each action is in brackets.
+        #[derive(Clone, Copy, PartialEq)]
+        pub enum Action {
+            $(
+                #[doc = concat!($description $(, " [", $keys, "]")?)]
+                $action,
+            )*
+        }
+
+        impl Action {
+            /// Returns the action with the specified name.
+            pub fn from_name(name: &str) -> Option<Self>
+            {
+                match name {
+                    $($name => Some(Action::$action),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the name of the action, as used in the config file.
+            pub fn name(self) -> &'static str
+            {
+                match self {
+                    $(Action::$action => $name,)*
+                }
+            }
+
+            /// Returns the description of the action.
+            pub fn description(self) -> &'static str
+            {
+                match self {
+                    $(Action::$action => $description,)*
+                }
+            }
+        }
+
+        /// All the actions, in the order they are listed in the help.
+        pub const ACTIONS: &[Action] = &[$(Action::$action,)*];
+    };
+}
+
+actions! {
+    Quit, "quit", "Quits the editor, unless there are unsaved changes.", "q";
+    ForceQuit, "force-quit", "Quits the editor, discarding the unsaved changes.", "Q";
+    Left, "left", "Moves the cursor left.", "h, Left";
+    Down, "down", "Moves the cursor down.", "j, Down";
+    Up, "up", "Moves the cursor up.", "k, Up";
+    Right, "right", "Moves the cursor right.", "l, Right";
+    LineStart, "line-start", "Moves the cursor to the start of the line.", "0, Home";
+    LineEnd, "line-end", "Moves the cursor to the end of the line.", "$, End";
+    NextRun, "next-run", "Moves the cursor to the next run of equal bytes.", "w";
+    PrevRun, "prev-run", "Moves the cursor to the start of the run of equal bytes, or the previous one.", "b";
+    Top, "top", "Goes to the start of the file.", "Shift-Home";
+    Bottom, "bottom", "Goes to the end of the file.", "G, Shift-End";
+    GotoPrefix, "goto-prefix", "Starts `gg` (start of the file), `go` (typed offset) or `gR` (replace bytes).", "g";
+    NextPrefix, "next-prefix", "Starts `]d` (next difference) or `]z` (next non-zero byte).", "]";
+    PrevPrefix, "prev-prefix", "Starts `[d` (previous difference) or `[z` (previous non-zero byte).", "[";
+    RegisterPrefix, "register-prefix", "Starts `\"N`, which selects the numbered register pasted by the next paste.", "\"";
+    Undo, "undo", "Undoes the last change.", "u";
+    Redo, "redo", "Redoes the last undone change.", "Ctrl-R";
+    ScrollUp, "scroll-up", "Scrolls up by a line.", "Ctrl-U";
+    ScrollDown, "scroll-down", "Scrolls down by a line.", "d";
+    PageUp, "page-up", "Scrolls up by a page.", "Ctrl-B, PageUp";
+    PageDown, "page-down", "Scrolls down by a page.", "Ctrl-F, PageDown";
+    JumpBack, "jump-back", "Moves back in the jumplist.", "Ctrl-O";
+    JumpForward, "jump-forward", "Moves forward in the jumplist.", "Ctrl-P";
+    Mark, "mark", "Sets a mark.", "m";
+    JumpToMark, "jump-to-mark", "Jumps to a mark.", "`";
+    SwitchView, "switch-view", "Switches between the views.", "t";
+    SwitchPane, "switch-pane", "Switches between the hex and the canonical pane.", "Tab";
+    Replace, "replace", "Replaces the byte under the cursor.", "r";
+    ReplaceMany, "replace-many", "Enters the replace mode.", "R";
+    Insert, "insert", "Enters the insert mode.", "i";
+    Delete, "delete", "Deletes the byte under the cursor.", "x";
+    Repeat, "repeat", "Repeats the last edit.", ".";
+    Inspector, "inspector", "Shows or hides the data inspector.", "I";
+    Visual, "visual", "Starts or ends the selection.", "v";
+    Yank, "yank", "Copies the selection.", "y";
+    Paste, "paste", "Pastes over the bytes at the cursor.", "p";
+    PasteInsert, "paste-insert", "Inserts the pasted bytes at the cursor.", "P";
+    Cancel, "cancel", "Cancels the selection.", "Esc";
+    Command, "command", "Opens the command line.", ":";
+    Search, "search", "Opens the search prompt.", "/";
+    SearchNext, "search-next", "Moves to the next match.", "n";
+    SearchPrev, "search-prev", "Moves to the previous match.", "N";
+    Help, "help", "Shows the key mappings and the commands.", "F1";
+    Nop, "nop", "Does nothing. Used for unmapping keys.";
+}
+
+/// The special keys, which are not remappable, with their names and actions.
+pub const SPECIAL_KEYS: [(Input, &str, Action); 11] = [
+    (Input::KeyLeft, "Left", Action::Left),
+    (Input::KeyDown, "Down", Action::Down),
+    (Input::KeyUp, "Up", Action::Up),
+    (Input::KeyRight, "Right", Action::Right),
+    (Input::KeyPPage, "PageUp", Action::PageUp),
+    (Input::KeyNPage, "PageDown", Action::PageDown),
+    (Input::KeyHome, "Home", Action::LineStart),
+    (Input::KeyEnd, "End", Action::LineEnd),
+    (Input::KeySHome, "Shift-Home", Action::Top),
+    (Input::KeySEnd, "Shift-End", Action::Bottom),
+    (Input::KeyF1, "F1", Action::Help),
+];
+
+
+/// Parses a key: a single character, `<C-x>` for a control character, or one of `<Esc>`,
+/// `<Tab>`, `<Space>`, `<lt>`.
+pub fn parse_key(key: &str) -> Option<char>
+{
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c);
+    }
+
+    match key.to_ascii_lowercase().as_str() {
+        "<esc>" => Some('\u{1b}'),
+        "<tab>" => Some('\t'),
+        "<space>" => Some(' '),
+        "<lt>" => Some('<'),
+        k if k.len() == 5 && k.starts_with("<c-") && k.ends_with('>') => {
+            let c = k.as_bytes()[3];
+            if c.is_ascii_lowercase() {
+                Some((c - b'a' + 1) as char)
+            } else {
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Returns the name of a key, in the notation of the help.
+pub fn key_name(key: char) -> String
+{
+    match key {
+        '\u{1b}' => String::from("Esc"),
+        '\t' => String::from("Tab"),
+        ' ' => String::from("Space"),
+        c if (c as u32) < 0x20 => format!("Ctrl-{}", (c as u8 + b'A' - 1) as char),
+        c => c.to_string(),
+    }
+}
+
+/// Returns the default mappings of the character keys.
+pub fn default_keymap() -> HashMap<char, Action>
+{
+    [
+        ('q', Action::Quit),
+        ('Q', Action::ForceQuit),
+        ('h', Action::Left),
+        ('j', Action::Down),
+        ('k', Action::Up),
+        ('l', Action::Right),
+        ('0', Action::LineStart),
+        ('$', Action::LineEnd),
+        ('w', Action::NextRun),
+        ('b', Action::PrevRun),
+        ('G', Action::Bottom),
+        ('g', Action::GotoPrefix),
+        (']', Action::NextPrefix),
+        ('[', Action::PrevPrefix),
+        ('"', Action::RegisterPrefix),
+        ('u', Action::Undo),
+        ('\u{12}', Action::Redo),
+        ('\u{15}', Action::ScrollUp),
+        ('d', Action::ScrollDown),
+        ('\u{2}', Action::PageUp),
+        ('\u{6}', Action::PageDown),
+        ('\u{f}', Action::JumpBack),
+        ('\u{10}', Action::JumpForward),
+        ('m', Action::Mark),
+        ('`', Action::JumpToMark),
+        ('t', Action::SwitchView),
+        ('\t', Action::SwitchPane),
+        ('r', Action::Replace),
+        ('R', Action::ReplaceMany),
+        ('i', Action::Insert),
+        ('x', Action::Delete),
+        ('.', Action::Repeat),
+        ('I', Action::Inspector),
+        ('v', Action::Visual),
+        ('y', Action::Yank),
+        ('p', Action::Paste),
+        ('P', Action::PasteInsert),
+        ('\u{1b}', Action::Cancel),
+        (':', Action::Command),
+        ('/', Action::Search),
+        ('n', Action::SearchNext),
+        ('N', Action::SearchPrev),
+    ].iter().copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_names_round_trip()
+    {
+        for &action in ACTIONS {
+            assert!(Action::from_name(action.name()) == Some(action));
+            assert!(!action.description().is_empty());
+        }
+        assert!(Action::from_name("no-such-action").is_none());
+    }
+
+    #[test]
+    fn tab_switches_panes()
+    {
+        let keymap = default_keymap();
+        assert!(keymap[&'\t'] == Action::SwitchPane);
+        assert!(keymap[&'\u{10}'] == Action::JumpForward);
+        assert_eq!(parse_key("<Tab>"), Some('\t'));
+    }
+}
@@ -0,0 +1,16 @@
+#![allow(dead_code)]
+//! The editor behind the hexvi binary.
+//!
+//! [`document`] holds the bytes of a file and the pending edits, and searches them, without a
+//! terminal. [`widget`] and [`editor`] show them with curses.
+
+pub mod charset;
+pub mod document;
+pub mod editor;
+pub mod hash;
+pub mod keymap;
+pub mod options;
+pub mod screen;
+pub mod template;
+pub mod util;
+pub mod widget;
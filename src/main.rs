@@ -1,14 +1,6 @@
-#![allow(dead_code)]
-use std::fs::{OpenOptions, File};
-use pancurses::Input;
-
-mod editor;
-mod options;
-mod util;
-mod widget;
-
-use editor::Editor;
-use widget::Direction;
+use std::io::IsTerminal;
+use hexvi::editor::{self, Editor};
+use hexvi::options;
 
 fn main()
 {
@@ -33,90 +25,50 @@ fn main()
         std::process::exit(1);
     }
 
-    // Attempt to open the file as rw. If failed, attempt to open it as ro. Else, exit.
+    if options.from_stdin && std::io::stdin().is_terminal() {
+        eprintln!("{}: standard input is a terminal", argv[0]);
+        options::usage();
+        std::process::exit(1);
+    }
 
-    let infile = match OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .open(&options.infile_name)
-    {
-        Ok(f) => f,
-        Err(_) => match File::open(&options.infile_name) {
-            Ok(f) => {
-                options.ro = true;
-                f
-            },
+    // Open all the files before the screen is taken over, so that the errors can be printed.
+    let mut other_buffers = Vec::new();
+    for path in &options.other_files {
+        let mut config = options.clone();
+        config.infile_name = path.clone();
+        config.from_stdin = false;
+        config.output = None;
+        match editor::open_input(&mut config) {
+            Ok(file) => other_buffers.push((file, config)),
             Err(e) => {
-                eprintln!("{}: {}: {}", argv[0], options.infile_name.to_str().unwrap(), &e);
+                eprintln!("{}: {}", argv[0], &e);
                 std::process::exit(1);
             },
+        }
+    }
+    let infile = match editor::open_input(&mut options) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: {}", argv[0], &e);
+            std::process::exit(1);
         },
     };
 
     // Initialise the editor.
     let mut editor = Editor::init(infile, options);
+    for (file, config) in other_buffers {
+        editor.add_buffer(file, config);
+    }
 
     // Loop keyboard events.
     loop {
-        match editor.getch() {
-            Some(Input::Character(c)) => {
-                if c == 'q' {
-                    break;
-                } else if c == 'h' {
-                    editor.move_cursor(Direction::Left, 1);
-                } else if c == 'j' {
-                    editor.move_cursor(Direction::Down, 1);
-                } else if c == 'k' {
-                    editor.move_cursor(Direction::Up, 1);
-                } else if c == 'l' {
-                    editor.move_cursor(Direction::Right, 1);
-                } else if c == 'g' {
-                    editor.seek(0);
-                } else if c == 'G' {
-                    editor.seek(-1);
-                } else if c == 'u' {
-                    editor.scroll(Direction::Up, 1);
-                } else if c == 'd' {
-                    editor.scroll(Direction::Down, 1);
-                } else if c == '\t' {
-                    editor.switch_pane();
-                } else if c == 't' {
-                    editor.switch_view();
-                } else if c == 'r' {
-                    editor.replace().ok();
-                } else if c == 'R' {
-                    editor.replace_many().ok();
-                //} else if c == ':' {
-                    //editor.command();
-                }
-            },
-            Some(Input::KeyRight) => {
-                editor.move_cursor(Direction::Right, 1);
-            },
-            Some(Input::KeyLeft) => {
-                editor.move_cursor(Direction::Left, 1);
-            },
-            Some(Input::KeyUp) => {
-                editor.move_cursor(Direction::Up, 1);
-            },
-            Some(Input::KeyDown) => {
-                editor.move_cursor(Direction::Down, 1);
-            },
-            Some(Input::KeyHome) => {
-                editor.seek(0);
-            },
-            Some(Input::KeyEnd) => {
-                editor.seek(-1);
-            },
-            Some(Input::KeyResize) => {
-                pancurses::resize_term(0, 0);
+        if let Some(key) = editor.getch() {
+            if editor.handle_key(key) {
+                break;
             }
-            Some(_) => (),
-            None => (),
         }
-        editor.refresh();
     }
 
     editor.end()
 }
+
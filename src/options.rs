@@ -1,24 +1,117 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use getopts::Options;
 use anyhow::anyhow;
+use crate::charset::Charset;
+use crate::keymap::{self, Action};
+use crate::util;
+use crate::widget::{self, OffsetBase};
 
 /// Holds various configuration options.
+#[derive(Clone)]
 pub struct Config {
     pub has_infile: bool,
     pub infile_name: PathBuf,
-    pub ro: bool
+    pub other_files: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub from_stdin: bool,
+    pub temporary: bool,
+    pub ro: bool,
+    pub copy: bool,
+    pub undo_levels: usize,
+    pub bytes_per_line: usize,
+    pub group_size: usize,
+    pub start_offset: u64,
+    pub charset: Charset,
+    pub ruler: bool,
+    pub offsets: bool,
+    pub canon: bool,
+    pub wrap: bool,
+    pub separator: String,
+    pub box_separators: bool,
+    pub offset_base: OffsetBase,
+    pub find: Option<Vec<Option<u8>>>,
+    pub read_limit: usize,
+    pub mmap: bool,
+    pub keymap: HashMap<char, Action>
 }
 
-/// Parses the cmdline options and returns Config.
+impl Default for Config {
+    fn default() -> Self
+    {
+        Self {
+            has_infile: false,
+            infile_name: PathBuf::default(),
+            other_files: Vec::new(),
+            output: None,
+            from_stdin: false,
+            temporary: false,
+            ro: false,
+            copy: false,
+            undo_levels: 1000,
+            bytes_per_line: 16,
+            group_size: 2,
+            start_offset: 0,
+            charset: Charset::Ascii,
+            ruler: false,
+            offsets: true,
+            canon: true,
+            wrap: false,
+            separator: String::from(widget::DEFAULT_SEPARATOR),
+            box_separators: false,
+            offset_base: OffsetBase::Hex,
+            find: None,
+            read_limit: 1 << 20,
+            mmap: false,
+            keymap: keymap::default_keymap()
+        }
+    }
+}
+
+/// Parses the cmdline options and the config file, and returns Config.
 pub fn parse_options() -> anyhow::Result<Config>
 {
     let argv: Vec<String> = std::env::args().collect();
+    let mut config = parse_args(&argv[1..])?;
+
+    if let Some(path) = config_path() {
+        if path.exists() {
+            read_config(&path, &mut config.keymap)?;
+        }
+    }
+
+    // (The Rust Foundation, 2019)
+
+    Ok(config)
+}
 
+/// Parses the cmdline arguments, without the program name, and returns Config.
+fn parse_args(args: &[String]) -> anyhow::Result<Config>
+{
     let mut options = Options::new();
 
     options.optflag("h", "help", "display help");
+    options.optflag("R", "readonly", "open the file as read-only");
+    options.optopt("w", "width", "number of bytes per line", "N");
+    options.optopt("g", "group", "number of hex bytes between the spaces: 1, 2, 4 or 8", "N");
+    options.optopt("o", "offset", "start at the offset", "OFFSET");
+    options.optopt("O", "output", "write the changes to PATH, leaving the file as it is", "PATH");
+    options.optopt("f", "find", "start at the first match of the pattern", "PATTERN");
+    options.optopt("c", "charset", "character set of the canonical pane", "NAME");
+    options.optflag("", "copy", "edit a copy of a read-only file without asking");
+    options.optflag("", "ruler", "show the column numbers above the panes");
+    options.optflag("", "no-canon", "hide the canonical pane");
+    options.optflag("", "no-offsets", "hide the offset column");
+    options.optflag("", "wrap", "wrap the cursor around the ends of the file");
+    options.optopt("", "separator", "separator between the panes", "STRING");
+    options.optflag("", "box-separators", "draw the | of the separators as lines");
+    options.optopt("", "offsets", "base of the offsets: hex, dec or oct", "BASE");
+    options.optopt("", "bytes-read-limit", "maximum number of bytes read at once", "N");
+    #[cfg(feature = "mmap")]
+    options.optflag("", "mmap", "map the file into memory for search, diff and hash");
+    options.optopt("", "undolevels", "maximum number of changes that can be undone", "N");
 
-    let present_options = match options.parse(&argv[1..]) {
+    let present_options = match options.parse(args) {
         Ok(o) => o,
         Err(e) => {
             return Err(anyhow!("{}", &e));
@@ -26,35 +119,229 @@ pub fn parse_options() -> anyhow::Result<Config>
     };
 
     // Initiate the return config with default values.
-    let mut config = Config {
-        has_infile: false,
-        infile_name: PathBuf::default(),
-        ro: false
-    };
+    let mut config = Config::default();
 
     if present_options.opt_present("h") {
             usage();
             std::process::exit(0);
     };
 
-    // Get the non-option arg. (file name)
-    if !present_options.free.is_empty() {
-        config.infile_name = PathBuf::from(&present_options.free[0]);
+    if present_options.opt_present("R") {
+        config.ro = true;
+    };
+
+    if let Some(width) = present_options.opt_str("w") {
+        config.bytes_per_line = match width.parse() {
+            Ok(w) if w > 0 => w,
+            _ => return Err(anyhow!("{}: invalid number of bytes per line", width)),
+        };
+    };
+
+    if let Some(group) = present_options.opt_str("g") {
+        config.group_size = parse_group_size(&group)?;
+    };
+
+    if let Some(offset) = present_options.opt_str("o") {
+        config.start_offset = util::parse_offset(&offset)?;
+    };
+
+    if let Some(path) = present_options.opt_str("O") {
+        config.output = Some(PathBuf::from(path));
+    };
+
+    if let Some(pattern) = present_options.opt_str("f") {
+        config.find = Some(util::parse_pattern(&pattern)?);
+    };
+
+    if let Some(name) = present_options.opt_str("c") {
+        config.charset = match Charset::from_name(&name) {
+            Some(c) => c,
+            None => return Err(anyhow!("{}: unknown character set", name)),
+        };
+    };
+
+    if present_options.opt_present("copy") {
+        config.copy = true;
+    };
+
+    if present_options.opt_present("ruler") {
+        config.ruler = true;
+    };
+
+    if present_options.opt_present("no-canon") {
+        config.canon = false;
+    };
+
+    if present_options.opt_present("no-offsets") {
+        config.offsets = false;
+    };
+
+    if present_options.opt_present("wrap") {
+        config.wrap = true;
+    };
+
+    if let Some(separator) = present_options.opt_str("separator") {
+        // Each character takes up exactly one column.
+        if separator.is_empty() || !separator.bytes().all(util::check_printable) {
+            return Err(anyhow!("{:?}: the separator must be printable ASCII characters", separator));
+        }
+        config.separator = separator;
+    };
+
+    if present_options.opt_present("box-separators") {
+        config.box_separators = true;
+    };
+
+    if let Some(name) = present_options.opt_str("offsets") {
+        config.offset_base = match OffsetBase::from_name(&name) {
+            Some(b) => b,
+            None => return Err(anyhow!("{}: expected hex, dec or oct", name)),
+        };
+    };
+
+    if let Some(limit) = present_options.opt_str("bytes-read-limit") {
+        config.read_limit = match limit.parse() {
+            Ok(l) if l > 0 => l,
+            _ => return Err(anyhow!("{}: invalid read limit", limit)),
+        };
+    };
+
+    #[cfg(feature = "mmap")]
+    if present_options.opt_present("mmap") {
+        config.mmap = true;
+    };
+
+    if let Some(levels) = present_options.opt_str("undolevels") {
+        config.undo_levels = match levels.parse() {
+            Ok(l) => l,
+            Err(_) => return Err(anyhow!("{}: invalid number of undo levels", levels)),
+        };
+    };
+
+    // Get the non-option args. (file names)
+    if let Some((first, others)) = present_options.free.split_first() {
+        config.infile_name = PathBuf::from(first);
+        config.other_files = others.iter().map(PathBuf::from).collect();
         config.has_infile = true;
     };
 
-    // (The Rust Foundation, 2019)
+    if config.other_files.iter().any(|path| path.as_os_str() == "-") {
+        return Err(anyhow!("-: standard input can only be the first file"));
+    }
+
+    if config.infile_name.as_os_str() == "-" {
+        config.from_stdin = true;
+    }
 
     Ok(config)
 }
 
+/// Returns the path of the config file, in $XDG_CONFIG_HOME or ~/.config.
+fn config_path() -> Option<PathBuf>
+{
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(dir.join("hexvi").join("config"))
+}
+
+/// Reads the key mappings from the config file into the keymap.
+/// Each line is either `map KEY ACTION` or `unmap KEY`. Lines starting with `#` are ignored.
+fn read_config(path: &Path, keymap: &mut HashMap<char, Action>) -> anyhow::Result<()>
+{
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => return Err(anyhow!("{}: {}", path.display(), e)),
+    };
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |message: String| anyhow!("{}:{}: {}", path.display(), i + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["map", key, action] => {
+                let key = keymap::parse_key(key).ok_or_else(|| error(format!("{}: invalid key", key)))?;
+                let action = Action::from_name(action).ok_or_else(|| error(format!("{}: unknown action", action)))?;
+                keymap.insert(key, action);
+            },
+            ["unmap", key] => {
+                let key = keymap::parse_key(key).ok_or_else(|| error(format!("{}: invalid key", key)))?;
+                keymap.remove(&key);
+            },
+            _ => return Err(error(String::from("expected map KEY ACTION or unmap KEY"))),
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the number of hex bytes between the spaces.
+pub fn parse_group_size(s: &str) -> anyhow::Result<usize>
+{
+    match s.parse() {
+        Ok(g @ (1 | 2 | 4 | 8)) => Ok(g),
+        _ => Err(anyhow!("{}: expected a group size of 1, 2, 4 or 8", s)),
+    }
+}
+
 /// Prints the usage.
 pub fn usage()
 {
     let argv: Vec<String> = std::env::args().collect();
 
-    eprintln!("Usage: {} [OPTION]... FILE", argv[0]);
+    eprintln!("Usage: {} [OPTION]... FILE...", argv[0]);
+    eprintln!();
+    eprintln!("With FILE of -, read standard input. The files after the first are opened as buffers.");
+    eprintln!("Key mappings are read from $XDG_CONFIG_HOME/hexvi/config (map KEY ACTION, unmap KEY).");
     eprintln!();
     eprintln!("Options:");
-    eprintln!("  -h, --help  display help");
+    eprintln!("  -h, --help          display help");
+    eprintln!("  -R, --readonly      open the file as read-only");
+    eprintln!("  -c, --charset NAME  character set of the canonical pane (ascii, ebcdic, latin1)");
+    eprintln!("  -f, --find PATTERN  start at the first match of the pattern (hex with ?? for any byte, or \"-prefixed string)");
+    eprintln!("  -g, --group N       number of hex bytes between the spaces (1, 2, 4, 8; default: 2)");
+    eprintln!("  -o, --offset OFFSET start at the offset (decimal or 0x-prefixed hex)");
+    eprintln!("  -O, --output PATH   write the changes to PATH, leaving the file as it is");
+    eprintln!("  -w, --width N       number of bytes per line (default: 16)");
+    eprintln!("      --box-separators");
+    eprintln!("                      draw the | of the separators as lines");
+    eprintln!("      --copy          edit a copy of a read-only file without asking");
+    eprintln!("      --bytes-read-limit N");
+    eprintln!("                      maximum number of bytes read at once (default: 1048576)");
+    #[cfg(feature = "mmap")]
+    eprintln!("      --mmap          map the file into memory for search, diff and hash");
+    eprintln!("      --no-canon      hide the canonical pane");
+    eprintln!("      --no-offsets    hide the offset column");
+    eprintln!("      --offsets BASE  base of the offsets (hex, dec, oct; default: hex)");
+    eprintln!("      --ruler         show the column numbers above the panes");
+    eprintln!("      --separator STRING");
+    eprintln!("                      separator between the panes (default: \" | \")");
+    eprintln!("      --undolevels N  maximum number of changes that can be undone");
+    eprintln!("      --wrap          wrap the cursor around the ends of the file");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the arguments, given as strs.
+    fn parse(args: &[&str]) -> anyhow::Result<Config>
+    {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn readonly_flag()
+    {
+        assert!(!parse(&["file"]).unwrap().ro);
+        assert!(parse(&["-R", "file"]).unwrap().ro);
+        assert!(parse(&["--readonly", "file"]).unwrap().ro);
+    }
 }
@@ -0,0 +1,295 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use pancurses::chtype;
+
+/// The operations of a window that the views draw with.
+pub trait Screen: Sized {
+    /// Moves the cursor.
+    fn mv(&self, y: i32, x: i32);
+    /// Prints a string at the cursor.
+    fn printw<T: AsRef<str>>(&self, string: T);
+    /// Adds a character at the cursor.
+    fn addch(&self, ch: chtype);
+    /// Changes the attributes and the color pair of n characters, or the rest of the line if n is
+    /// -1.
+    fn mvchgat(&self, y: i32, x: i32, n: i32, attributes: chtype, color_pair: i16);
+    /// Clears the window, and repaints it on the next refresh.
+    fn clear(&self);
+    /// Clears the window.
+    fn erase(&self);
+    /// Shows the changes on the terminal.
+    fn refresh(&self);
+    /// Returns the height and the width of the window.
+    fn get_max_yx(&self) -> (i32, i32);
+    /// Returns the absolute coordinates of the top-left corner of the window.
+    fn get_beg_yx(&self) -> (i32, i32);
+    /// Returns a window inside this one, at coordinates relative to it.
+    fn derwin(&self, lines: i32, cols: i32, y: i32, x: i32) -> Result<Self, i32>;
+    /// Sets the color pair of the printed text.
+    fn color_set(&self, color_pair: i16);
+    /// Sets the background of the window.
+    fn bkgd(&self, ch: chtype);
+
+    /// Moves the cursor and prints a string.
+    fn mvprintw<T: AsRef<str>>(&self, y: i32, x: i32, string: T)
+    {
+        self.mv(y, x);
+        self.printw(string);
+    }
+
+    /// Returns the height of the window.
+    fn get_max_y(&self) -> i32
+    {
+        self.get_max_yx().0
+    }
+
+    /// Returns the width of the window.
+    fn get_max_x(&self) -> i32
+    {
+        self.get_max_yx().1
+    }
+
+    /// Returns the absolute line of the top of the window.
+    fn get_beg_y(&self) -> i32
+    {
+        self.get_beg_yx().0
+    }
+
+    /// Returns the absolute column of the left of the window.
+    fn get_beg_x(&self) -> i32
+    {
+        self.get_beg_yx().1
+    }
+}
+
+impl Screen for pancurses::Window {
+    fn mv(&self, y: i32, x: i32)
+    {
+        pancurses::Window::mv(self, y, x);
+    }
+
+    fn printw<T: AsRef<str>>(&self, string: T)
+    {
+        pancurses::Window::printw(self, string);
+    }
+
+    fn addch(&self, ch: chtype)
+    {
+        pancurses::Window::addch(self, ch);
+    }
+
+    fn mvchgat(&self, y: i32, x: i32, n: i32, attributes: chtype, color_pair: i16)
+    {
+        pancurses::Window::mvchgat(self, y, x, n, attributes, color_pair);
+    }
+
+    fn clear(&self)
+    {
+        pancurses::Window::clear(self);
+    }
+
+    fn erase(&self)
+    {
+        pancurses::Window::erase(self);
+    }
+
+    fn refresh(&self)
+    {
+        pancurses::Window::refresh(self);
+    }
+
+    fn get_max_yx(&self) -> (i32, i32)
+    {
+        pancurses::Window::get_max_yx(self)
+    }
+
+    fn get_beg_yx(&self) -> (i32, i32)
+    {
+        pancurses::Window::get_beg_yx(self)
+    }
+
+    fn derwin(&self, lines: i32, cols: i32, y: i32, x: i32) -> Result<Self, i32>
+    {
+        pancurses::Window::derwin(self, lines, cols, y, x)
+    }
+
+    fn color_set(&self, color_pair: i16)
+    {
+        pancurses::Window::color_set(self, color_pair);
+    }
+
+    fn bkgd(&self, ch: chtype)
+    {
+        pancurses::Window::bkgd(self, ch);
+    }
+}
+
+/// A character on a TestScreen, with its attributes and color pair.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TestCell {
+    pub ch: char,
+    pub attributes: chtype,
+    pub color_pair: i16
+}
+
+/// An in-memory screen, which records the drawn characters, for testing the views without a
+/// terminal. Windows derived from it draw on the same cells.
+pub struct TestScreen {
+    cells: Rc<RefCell<Vec<Vec<TestCell>>>>,
+    beg: (i32, i32),
+    size: (i32, i32),
+    cursor: Cell<(i32, i32)>,
+    color_pair: Cell<i16>
+}
+
+impl TestScreen {
+    /// Returns a new blank TestScreen.
+    pub fn new(lines: i32, cols: i32) -> Self
+    {
+        let blank = TestCell { ch: ' ', attributes: 0, color_pair: 0 };
+
+        Self {
+            cells: Rc::new(RefCell::new(vec![vec![blank; cols as usize]; lines as usize])),
+            beg: (0, 0),
+            size: (lines, cols),
+            cursor: Cell::new((0, 0)),
+            color_pair: Cell::new(0)
+        }
+    }
+
+    /// Returns the cell at the coordinates relative to the window.
+    pub fn cell(&self, y: i32, x: i32) -> TestCell
+    {
+        self.cells.borrow()[(self.beg.0 + y) as usize][(self.beg.1 + x) as usize]
+    }
+
+    /// Returns the text of a line of the window.
+    pub fn line(&self, y: i32) -> String
+    {
+        (0..self.size.1).map(|x| self.cell(y, x).ch).collect()
+    }
+
+    /// Writes a character at the cursor and advances it, wrapping at the end of the line.
+    fn put(&self, ch: char)
+    {
+        let (y, x) = self.cursor.get();
+        if y >= self.size.0 {
+            return;
+        }
+
+        if ch == '\n' {
+            // The rest of the line is cleared.
+            for x in x..self.size.1 {
+                self.set(y, x, ' ');
+            }
+            self.cursor.set((y + 1, 0));
+            return;
+        }
+
+        self.set(y, x, ch);
+        if x + 1 < self.size.1 {
+            self.cursor.set((y, x + 1));
+        } else {
+            self.cursor.set((y + 1, 0));
+        }
+    }
+
+    /// Writes a character at the coordinates relative to the window, in the current color pair.
+    fn set(&self, y: i32, x: i32, ch: char)
+    {
+        let mut cells = self.cells.borrow_mut();
+        cells[(self.beg.0 + y) as usize][(self.beg.1 + x) as usize] = TestCell {
+            ch,
+            attributes: 0,
+            color_pair: self.color_pair.get()
+        };
+    }
+}
+
+impl Screen for TestScreen {
+    fn mv(&self, y: i32, x: i32)
+    {
+        self.cursor.set((y, x));
+    }
+
+    fn printw<T: AsRef<str>>(&self, string: T)
+    {
+        for ch in string.as_ref().chars() {
+            self.put(ch);
+        }
+    }
+
+    fn addch(&self, ch: chtype)
+    {
+        // Only the character, in the low byte, is kept, not the attributes.
+        self.put(char::from((ch & 0xff) as u8));
+    }
+
+    fn mvchgat(&self, y: i32, x: i32, n: i32, attributes: chtype, color_pair: i16)
+    {
+        let end = if n < 0 { self.size.1 } else { (x + n).min(self.size.1) };
+        let mut cells = self.cells.borrow_mut();
+        for x in x..end {
+            let cell = &mut cells[(self.beg.0 + y) as usize][(self.beg.1 + x) as usize];
+            cell.attributes = attributes;
+            cell.color_pair = color_pair;
+        }
+        self.cursor.set((y, x));
+    }
+
+    fn clear(&self)
+    {
+        self.erase();
+    }
+
+    fn erase(&self)
+    {
+        for y in 0..self.size.0 {
+            for x in 0..self.size.1 {
+                self.set(y, x, ' ');
+            }
+        }
+        self.cursor.set((0, 0));
+    }
+
+    fn refresh(&self)
+    {
+    }
+
+    fn get_max_yx(&self) -> (i32, i32)
+    {
+        self.size
+    }
+
+    fn get_beg_yx(&self) -> (i32, i32)
+    {
+        self.beg
+    }
+
+    fn derwin(&self, lines: i32, cols: i32, y: i32, x: i32) -> Result<Self, i32>
+    {
+        // As in curses, 0 lines or columns extend the window to the edge of this one.
+        let lines = if lines == 0 { self.size.0 - y } else { lines };
+        let cols = if cols == 0 { self.size.1 - x } else { cols };
+        if y < 0 || x < 0 || lines <= 0 || cols <= 0 || y + lines > self.size.0 || x + cols > self.size.1 {
+            return Err(-1);
+        }
+
+        Ok(Self {
+            cells: Rc::clone(&self.cells),
+            beg: (self.beg.0 + y, self.beg.1 + x),
+            size: (lines, cols),
+            cursor: Cell::new((0, 0)),
+            color_pair: Cell::new(0)
+        })
+    }
+
+    fn color_set(&self, color_pair: i16)
+    {
+        self.color_pair.set(color_pair);
+    }
+
+    fn bkgd(&self, _ch: chtype)
+    {
+    }
+}
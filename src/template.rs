@@ -0,0 +1,156 @@
+use std::path::Path;
+use anyhow::anyhow;
+use crate::util;
+
+/// The type of a template field, which determines how its bytes are decoded.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FieldType {
+    /// An unsigned integer.
+    Unsigned { little_endian: bool },
+    /// A signed integer.
+    Signed { little_endian: bool },
+    /// A floating-point number.
+    Float { little_endian: bool },
+    /// A string of bytes, shown as hex.
+    Bytes,
+    /// A string of characters, shown with escapes for the non-printable bytes.
+    Str,
+}
+
+/// A named range of bytes, described by a template.
+#[derive(Clone)]
+pub struct Field {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    pub field_type: FieldType,
+}
+
+impl Field {
+    /// Returns true if the field contains the offset.
+    pub fn contains(&self, offset: u64) -> bool
+    {
+        offset >= self.offset && offset - self.offset < self.size
+    }
+
+    /// Decodes the bytes of the field. Strings of bytes and characters can be cut short.
+    pub fn decode(&self, bytes: &[u8]) -> String
+    {
+        // Numbers can't be decoded from a part of their bytes.
+        let numeric = !matches!(self.field_type, FieldType::Bytes | FieldType::Str);
+        if numeric && (bytes.len() as u64) < self.size {
+            return String::from("<past the end of the file>");
+        }
+
+        match self.field_type {
+            FieldType::Unsigned { little_endian } => int_value(bytes, little_endian).to_string(),
+            FieldType::Signed { little_endian } => {
+                // Sign-extend the value from the size of the field.
+                let shift = 64 - self.size * 8;
+                (((int_value(bytes, little_endian) << shift) as i64) >> shift).to_string()
+            },
+            FieldType::Float { little_endian } => {
+                let value = int_value(bytes, little_endian);
+                match self.size {
+                    4 => f32::from_bits(value as u32).to_string(),
+                    _ => f64::from_bits(value).to_string(),
+                }
+            },
+            FieldType::Bytes => hex::encode(bytes),
+            FieldType::Str => {
+                let mut s = String::from("\"");
+                for byte in bytes {
+                    if util::check_printable(*byte) {
+                        s.push(*byte as char);
+                    } else {
+                        s.push_str(&format!("\\x{:02x}", byte));
+                    }
+                }
+                s.push('"');
+                s
+            },
+        }
+    }
+}
+
+/// Returns the value of up to 8 bytes, as an unsigned integer.
+fn int_value(bytes: &[u8], little_endian: bool) -> u64
+{
+    let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+    if little_endian {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    }
+}
+
+/// Parses the type of a field, with an optional size. Returns the type and the size.
+fn parse_type(name: &str, size: Option<&str>) -> anyhow::Result<(FieldType, u64)>
+{
+    let (base, little_endian) = match name.strip_suffix("be") {
+        Some(base) => (base, false),
+        None => (name.strip_suffix("le").unwrap_or(name), true),
+    };
+
+    let (field_type, size) = match (base, size) {
+        ("u8" | "u16" | "u32" | "u64", None) => {
+            (FieldType::Unsigned { little_endian }, base[1..].parse::<u64>()? / 8)
+        },
+        ("i8" | "i16" | "i32" | "i64", None) => {
+            (FieldType::Signed { little_endian }, base[1..].parse::<u64>()? / 8)
+        },
+        ("f32" | "f64", None) => (FieldType::Float { little_endian }, base[1..].parse::<u64>()? / 8),
+        ("bytes", Some(size)) if name == base => (FieldType::Bytes, util::parse_offset(size)?),
+        ("str", Some(size)) if name == base => (FieldType::Str, util::parse_offset(size)?),
+        ("bytes" | "str", None) => return Err(anyhow!("{}: missing size", name)),
+        (_, Some(size)) => return Err(anyhow!("{}: unexpected size", size)),
+        _ => return Err(anyhow!("{}: unknown type", name)),
+    };
+
+    if size == 0 {
+        return Err(anyhow!("{}: the size must not be 0", name));
+    }
+
+    Ok((field_type, size))
+}
+
+/// Reads the fields of a template file, sorted by their offsets.
+/// Each line is `NAME OFFSET TYPE [SIZE]`, where TYPE is one of u8, u16, u32, u64, i8, i16, i32,
+/// i64, f32 and f64, optionally suffixed with `le` or `be`, or `bytes` or `str` followed by the
+/// size. Lines starting with `#` are ignored.
+pub fn read_template(path: &Path) -> anyhow::Result<Vec<Field>>
+{
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => return Err(anyhow!("{}: {}", path.display(), e)),
+    };
+
+    let mut fields = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |e: anyhow::Error| anyhow!("{}:{}: {}", path.display(), i + 1, e);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, offset, field_type, size) = match words.as_slice() {
+            [name, offset, field_type] => (name, offset, field_type, None),
+            [name, offset, field_type, size] => (name, offset, field_type, Some(*size)),
+            _ => return Err(error(anyhow!("expected NAME OFFSET TYPE [SIZE]"))),
+        };
+
+        let offset = util::parse_offset(offset).map_err(error)?;
+        let (field_type, size) = parse_type(field_type, size).map_err(error)?;
+        fields.push(Field {
+            name: name.to_string(),
+            offset,
+            size,
+            field_type,
+        });
+    }
+
+    fields.sort_by_key(|f| f.offset);
+
+    Ok(fields)
+}
@@ -1,32 +1,51 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
 use std::io::{Read, Write, Seek, SeekFrom};
-use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use anyhow::anyhow;
+use hex::FromHex;
 
-/// Reads a file into a Vec of bytes.
-pub fn freadn_to_vec(file: &mut File, size: usize) -> Result<Vec<u8>, std::io::Error>
+/// The size of the chunks read at once by freadn_to_vec, which reads the visible bytes.
+const WINDOW_CHUNK_SIZE: usize = 512;
+
+/// The size of the chunks read at once by the reads of large ranges.
+pub const LARGE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads up to size bytes from the position of a file into a Vec, in chunks of 512 bytes.
+/// The position is left as it was.
+pub fn freadn_to_vec<F: Read + Seek>(file: &mut F, size: usize) -> Result<Vec<u8>, std::io::Error>
 {
-    let orig_position = file.seek(SeekFrom::Current(0))?;
+    freadn_to_vec_chunked(file, size, WINDOW_CHUNK_SIZE)
+}
+
+/// Reads up to size bytes from the position of a file into a Vec, in chunks of at most chunk_size
+/// bytes. The position is left as it was.
+pub fn freadn_to_vec_chunked<F>(file: &mut F, size: usize, chunk_size: usize) -> Result<Vec<u8>, std::io::Error>
+where
+    F: Read + Seek
+{
+    // Nothing is read, and the position is left as it is.
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+
+    let orig_position = file.stream_position()?;
 
-    let mut all_read = 0;
     let mut vector: Vec<u8> = Vec::new();
-    let mut buf: [u8; 512]  = [0; 512];
+    let mut buf = vec![0; chunk_size.min(size)];
 
-    loop {
-        let read = file.read(&mut buf)?;
+    while vector.len() < size {
+        // Never read past the requested size.
+        let wanted = buf.len().min(size - vector.len());
+        let read = file.read(&mut buf[..wanted])?;
         // EOF
         if read == 0 {
-            break
-        } else {
-            // Still more data to read.
-            if all_read + read <= size {
-                all_read += read;
-                vector.extend_from_slice(&buf[..read]);
-            // If we already read more than requested.
-            } else {
-                vector.extend(&buf[..(size - all_read)]);
-                break;
-            }
+            break;
         }
+        vector.extend_from_slice(&buf[..read]);
     }
 
     // Reset the seek back to its position.
@@ -35,13 +54,269 @@ pub fn freadn_to_vec(file: &mut File, size: usize) -> Result<Vec<u8>, std::io::E
     Ok(vector)
 }
 
+/// Reads a range of a file into a writer, in chunks of at most chunk_size bytes.
+/// Returns the number of bytes read, which is less than size if the file ends first.
+pub fn fread_range_to_writer<F, W>(
+    file: &mut F,
+    offset: u64,
+    size: u64,
+    out: &mut W,
+    chunk_size: usize
+) -> Result<u64, std::io::Error>
+where
+    F: Read + Seek,
+    W: Write
+{
+    let orig_position = file.stream_position()?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut all_read = 0;
+    let mut buf = vec![0; chunk_size.min(size as usize)];
+
+    while all_read < size {
+        let to_read = (size - all_read).min(buf.len() as u64) as usize;
+        let read = file.read(&mut buf[..to_read])?;
+        // EOF
+        if read == 0 {
+            break;
+        }
+        out.write_all(&buf[..read])?;
+        all_read += read as u64;
+    }
+
+    // Reset the seek back to its position.
+    file.seek(SeekFrom::Start(orig_position))?;
+
+    Ok(all_read)
+}
+
+/// Copies the range [start, end) of a file into a writer, in chunks of at most chunk_size bytes.
+/// The seek is restored afterwards. Returns the number of bytes copied.
+pub fn copy_range<W: Write>(
+    file: &mut File,
+    start: u64,
+    end: u64,
+    out: &mut W,
+    chunk_size: usize
+) -> Result<u64, std::io::Error>
+{
+    fread_range_to_writer(file, start, end.saturating_sub(start), out, chunk_size)
+}
+
+/// Reads stdin into an unlinked temporary file, so that it can be seeked, and reopens stdin from
+/// the terminal, so that the keyboard can still be read.
+pub fn stdin_to_temp_file() -> Result<File, std::io::Error>
+{
+    let file = copy_to_temp_file(&mut std::io::stdin().lock())?;
+
+    let tty = File::open("/dev/tty")?;
+    // SAFETY: both descriptors are open, and only the stdin descriptor is replaced.
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(file)
+}
+
+/// Copies everything read from a reader into a temporary file, which is deleted when it's closed.
+pub fn copy_to_temp_file<R: Read>(reader: &mut R) -> Result<File, std::io::Error>
+{
+    // The process can have more than one temporary file.
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("hexvi-{}-{}", std::process::id(), count));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // The file lives on until it's closed.
+    std::fs::remove_file(&path)?;
+
+    std::io::copy(reader, &mut file)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(file)
+}
+
+/// Creates the file at `path` and fills it with `write`, which returns the number of bytes written.
+/// An existing file is only replaced if `overwrite` is set, and then by writing a new file next to
+/// it and renaming it over it, so the old one stays whole, and readable, until the end. Special
+/// files, like devices and pipes, can't be replaced and are written into.
+pub fn write_new_file<F>(path: &Path, overwrite: bool, write: F) -> anyhow::Result<(File, u64)>
+    where F: FnOnce(&mut File) -> anyhow::Result<u64>
+{
+    let special = std::fs::metadata(path).map(|m| !m.is_file()).unwrap_or(false);
+    if overwrite && special {
+        let mut file = match OpenOptions::new().write(true).open(path) {
+            Ok(f) => f,
+            Err(e) => return Err(anyhow!("{}: {}", path.display(), e)),
+        };
+        let written = write(&mut file)?;
+        return Ok((file, written));
+    }
+
+    let target = if overwrite {
+        let name = match path.file_name() {
+            Some(n) => n.to_string_lossy(),
+            None => return Err(anyhow!("{}: not a file name", path.display())),
+        };
+        path.with_file_name(format!(".{}.hexvi-{}", name, std::process::id()))
+    } else {
+        path.to_path_buf()
+    };
+    let mut file = match OpenOptions::new().read(true).write(true).create_new(true).open(&target) {
+        Ok(f) => f,
+        Err(e) => return Err(anyhow!("{}: {}", target.display(), e)),
+    };
+
+    let fill = || -> anyhow::Result<u64> {
+        let written = write(&mut file)?;
+        if overwrite {
+            if let Ok(meta) = std::fs::metadata(path) {
+                file.set_permissions(meta.permissions())?;
+            }
+        }
+        file.sync_all()?;
+        if overwrite {
+            std::fs::rename(&target, path)?;
+        }
+        Ok(written)
+    };
+    match fill() {
+        Ok(written) => Ok((file, written)),
+        Err(e) => {
+            let _ = std::fs::remove_file(&target);
+            Err(e)
+        },
+    }
+}
+
+/// Returns whether the path names the file of the metadata, through symlinks and hard links. A path
+/// that doesn't exist names no file.
+pub fn names_file(path: &Path, meta: &std::fs::Metadata) -> bool
+{
+    use std::os::unix::fs::MetadataExt;
+    match std::fs::metadata(path) {
+        Ok(m) => m.dev() == meta.dev() && m.ino() == meta.ino(),
+        Err(_) => false,
+    }
+}
+
+/// Opens a file as read-write, creating it if it doesn't exist, or as read-only if that fails or if
+/// `read_only` is set. Returns the file and whether it is read-only.
+pub fn open_file(path: &Path, read_only: bool) -> anyhow::Result<(File, bool)>
+{
+    if !read_only {
+        let rw = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path);
+        if let Ok(file) = rw {
+            return Ok((file, false));
+        }
+    }
+
+    match File::open(path) {
+        Ok(file) => Ok((file, true)),
+        Err(e) => Err(anyhow!("{}: {}", path.display(), e)),
+    }
+}
+
 /// Converts a byte to its canonical representation.
 pub fn check_printable(byte: u8) -> bool
 {
-    if byte >= 0x20 && byte < 0x7f {
-        true
-    } else {
-        false
+    (0x20..0x7f).contains(&byte)
+}
+
+/// Returns the Shannon entropy of the bytes, from 0 to 8 bits per byte. Compressed or encrypted
+/// data is close to 8.
+pub fn entropy(bytes: &[u8]) -> f64
+{
+    let mut counts = [0usize; 256];
+    for byte in bytes {
+        counts[*byte as usize] += 1;
+    }
+
+    let len = bytes.len() as f64;
+    counts.iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// Parses an offset, either decimal or hexadecimal. (prefixed with `0x`)
+pub fn parse_offset(s: &str) -> anyhow::Result<u64>
+{
+    let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    };
+
+    result.map_err(|_| anyhow!("{}: invalid offset", s))
+}
+
+/// Returns true if the text is an offset, or the start of one.
+pub fn is_partial_offset(text: &str) -> bool
+{
+    let digits = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => text.chars().all(|c| c.is_ascii_digit()),
+    };
+
+    // The offset must also fit in 64 bits.
+    digits && (text.len() <= 2 || parse_offset(text).is_ok())
+}
+
+/// Parses a search pattern, either hex bytes, or a literal string if it starts with `"`.
+/// In hex bytes, `??` matches any byte.
+pub fn parse_pattern(s: &str) -> anyhow::Result<Vec<Option<u8>>>
+{
+    let pattern = match s.strip_prefix('"') {
+        Some(string) => Some(string.bytes().map(Some).collect()),
+        None => parse_hex_pattern(&s.split_whitespace().collect::<String>()),
+    };
+
+    match pattern {
+        Some(p) if !p.is_empty() => Ok(p),
+        _ => Err(anyhow!("{}: invalid search pattern", s)),
+    }
+}
+
+/// Parses a string of hex bytes, where `??` is a byte that matches any byte.
+fn parse_hex_pattern(s: &str) -> Option<Vec<Option<u8>>>
+{
+    // An odd digit at the end is a chunk that can't be parsed.
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            b"??" => Some(None),
+            _ => Vec::from_hex(pair).ok().map(|byte| Some(byte[0])),
+        })
+        .collect()
+}
+
+/// Returns true if the bytes match the search pattern.
+pub fn matches_pattern(bytes: &[u8], pattern: &[Option<u8>]) -> bool
+{
+    bytes.len() == pattern.len()
+        && bytes.iter().zip(pattern).all(|(byte, p)| p.is_none() || *p == Some(*byte))
+}
+
+/// Parses a string of hex bytes, which may be separated by whitespace.
+pub fn parse_hex_bytes(s: &str) -> anyhow::Result<Vec<u8>>
+{
+    let hex_string: String = s.split_whitespace().collect();
+    match Vec::from_hex(&hex_string) {
+        Ok(b) if !b.is_empty() => Ok(b),
+        Ok(_) => Err(anyhow!("missing bytes")),
+        Err(hex::FromHexError::OddLength) => Err(anyhow!("{}: odd number of hex digits", hex_string)),
+        Err(_) => Err(anyhow!("{}: invalid hex string", hex_string)),
     }
 }
 
@@ -76,3 +351,244 @@ pub fn popen(process: &str, args: &[&str], data: Vec<u8>) -> Result<Output, i32>
 
     Ok(output)
 }
+
+/// Returns the path of the swap file of a file: a hidden `.NAME.hexvi.swp` in the same directory.
+pub fn swap_path(path: &Path) -> PathBuf
+{
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.hexvi.swp", name))
+}
+
+/// Returns the path of a new writable copy of a file: `NAME.copy` in the same directory, or
+/// `NAME.copy.N` with the lowest N that doesn't exist yet.
+pub fn copy_path(path: &Path) -> PathBuf
+{
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut copy = path.with_file_name(format!("{}.copy", name));
+    let mut n = 1;
+    while copy.symlink_metadata().is_ok() {
+        copy = path.with_file_name(format!("{}.copy.{}", name, n));
+        n += 1;
+    }
+
+    copy
+}
+
+/// Writes the pending edits into a swap file, one `OFFSET BYTE` pair of hex numbers per line.
+pub fn write_swap(path: &Path, edits: &HashMap<u64, u8>) -> Result<(), std::io::Error>
+{
+    let mut offsets: Vec<u64> = edits.keys().copied().collect();
+    offsets.sort_unstable();
+
+    let mut out = std::io::BufWriter::new(File::create(path)?);
+    for offset in offsets {
+        writeln!(out, "{:x} {:02x}", offset, edits[&offset])?;
+    }
+    out.flush()
+}
+
+/// Reads the pending edits from a swap file.
+pub fn read_swap(path: &Path) -> anyhow::Result<HashMap<u64, u8>>
+{
+    let contents = std::fs::read_to_string(path)?;
+
+    let mut edits = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let parsed = line.split_once(' ').and_then(|(offset, byte)| {
+            Some((u64::from_str_radix(offset, 16).ok()?, u8::from_str_radix(byte, 16).ok()?))
+        });
+        match parsed {
+            Some((offset, byte)) => edits.insert(offset, byte),
+            None => return Err(anyhow!("{}:{}: invalid swap file entry", path.display(), i + 1)),
+        };
+    }
+
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn freadn_past_the_end()
+    {
+        let bytes: Vec<u8> = (0..100).collect();
+        let mut file = Cursor::new(&bytes);
+        file.set_position(90);
+
+        assert_eq!(freadn_to_vec(&mut file, 50).unwrap(), &bytes[90..]);
+        assert_eq!(file.position(), 90);
+    }
+
+    #[test]
+    fn freadn_on_the_chunk_boundary()
+    {
+        let bytes: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        let mut file = Cursor::new(&bytes);
+        file.set_position(7);
+
+        assert_eq!(freadn_to_vec(&mut file, 512).unwrap(), &bytes[7..519]);
+        assert_eq!(freadn_to_vec(&mut file, 1024).unwrap(), &bytes[7..1031]);
+        assert_eq!(freadn_to_vec(&mut file, 513).unwrap(), &bytes[7..520]);
+        assert_eq!(file.position(), 7);
+    }
+
+    #[test]
+    fn freadn_zero_bytes()
+    {
+        let mut file = Cursor::new(vec![1, 2, 3]);
+        file.set_position(2);
+        assert!(freadn_to_vec(&mut file, 0).unwrap().is_empty());
+        assert_eq!(file.position(), 2);
+
+        let mut empty = Cursor::new(Vec::new());
+        assert!(freadn_to_vec(&mut empty, 0).unwrap().is_empty());
+        assert!(freadn_to_vec(&mut empty, 16).unwrap().is_empty());
+    }
+
+    #[test]
+    fn copy_range_in_chunks()
+    {
+        let bytes: Vec<u8> = (0..100).collect();
+        let mut file = copy_to_temp_file(&mut Cursor::new(&bytes)).unwrap();
+        file.seek(SeekFrom::Start(42)).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(copy_range(&mut file, 3, 50, &mut out, 7).unwrap(), 47);
+        assert_eq!(out, &bytes[3..50]);
+        assert_eq!(file.stream_position().unwrap(), 42);
+
+        // The range is cut off at the end of the file.
+        out.clear();
+        assert_eq!(copy_range(&mut file, 90, 200, &mut out, 7).unwrap(), 10);
+        assert_eq!(out, &bytes[90..]);
+    }
+
+    #[test]
+    fn wildcards_at_the_ends_of_patterns()
+    {
+        assert_eq!(parse_pattern("??4142").unwrap(), vec![None, Some(0x41), Some(0x42)]);
+        assert_eq!(parse_pattern("41 42 ??").unwrap(), vec![Some(0x41), Some(0x42), None]);
+        assert_eq!(parse_pattern("????").unwrap(), vec![None, None]);
+        assert!(parse_pattern("41?").is_err());
+        assert!(parse_pattern("").is_err());
+
+        let leading = parse_pattern("??4142").unwrap();
+        assert!(matches_pattern(b"xAB", &leading));
+        assert!(matches_pattern(b"\0AB", &leading));
+        assert!(!matches_pattern(b"xAC", &leading));
+        assert!(!matches_pattern(b"AB", &leading));
+
+        let trailing = parse_pattern("4142??").unwrap();
+        assert!(matches_pattern(b"ABx", &trailing));
+        assert!(!matches_pattern(b"xBx", &trailing));
+        assert!(!matches_pattern(b"ABxy", &trailing));
+    }
+
+    #[test]
+    fn literal_patterns()
+    {
+        assert_eq!(parse_pattern("\"a?").unwrap(), vec![Some(b'a'), Some(b'?')]);
+        assert!(parse_pattern("\"").is_err());
+    }
+
+    #[test]
+    fn new_file_replaces_the_file_it_is_read_from()
+    {
+        let path = std::env::temp_dir().join(format!("hexvi-test-{}-replace", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        let mut source = File::open(&path).unwrap();
+
+        let err = write_new_file(&path, false, |_| Ok(0)).unwrap_err();
+        assert!(err.to_string().contains("exists"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"abc");
+
+        let (_, written) = write_new_file(&path, true, |out| Ok(std::io::copy(&mut source, out)?)).unwrap();
+        assert_eq!(written, 3);
+        assert_eq!(std::fs::read(&path).unwrap(), b"abc");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unwritable_file_is_opened_readonly()
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("hexvi-test-{}-unwritable", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+
+        // Root, or any process that may override the permissions, can write to the file anyway.
+        if OpenOptions::new().write(true).open(&path).is_ok() {
+            eprintln!("skipped: the permissions of {} are not enforced", path.display());
+        } else {
+            let (mut file, ro) = open_file(&path, false).unwrap();
+            assert!(ro);
+            assert!(file.write_all(b"x").is_err());
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn directory_is_opened_readonly()
+    {
+        // Nobody can open a directory for writing.
+        let (_, ro) = open_file(&std::env::temp_dir(), false).unwrap();
+        assert!(ro);
+
+        let path = std::env::temp_dir().join(format!("hexvi-test-{}-readonly", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        let (mut file, ro) = open_file(&path, true).unwrap();
+        assert!(ro);
+        assert!(file.write_all(b"x").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn copy_path_skips_existing_copies()
+    {
+        let path = std::env::temp_dir().join(format!("hexvi-test-{}-copied", std::process::id()));
+        let first = copy_path(&path);
+        assert_eq!(first, path.with_file_name(format!("{}.copy", path.file_name().unwrap().to_string_lossy())));
+
+        std::fs::write(&first, b"").unwrap();
+        let second = copy_path(&path);
+        assert_eq!(second, first.with_extension("copy.1"));
+
+        std::fs::remove_file(&first).unwrap();
+    }
+
+    #[test]
+    fn hard_link_names_the_same_file()
+    {
+        let path = std::env::temp_dir().join(format!("hexvi-test-{}-linked", std::process::id()));
+        let link = path.with_extension("link");
+        let other = path.with_extension("other");
+        std::fs::write(&path, b"abc").unwrap();
+        std::fs::write(&other, b"abc").unwrap();
+        std::fs::hard_link(&path, &link).unwrap();
+
+        let meta = File::open(&path).unwrap().metadata().unwrap();
+        assert!(names_file(&path, &meta));
+        assert!(names_file(&link, &meta));
+        assert!(!names_file(&other, &meta));
+        assert!(!names_file(&path.with_extension("missing"), &meta));
+
+        for p in [&path, &link, &other] {
+            std::fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn special_file_is_written_into()
+    {
+        let null = Path::new("/dev/null");
+        let (_, written) = write_new_file(null, true, |out| Ok(out.write(b"abc")? as u64)).unwrap();
+        assert_eq!(written, 3);
+        assert!(!std::fs::metadata(null).unwrap().is_file());
+    }
+}
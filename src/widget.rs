@@ -1,15 +1,37 @@
-use std::io::{Write, Seek, SeekFrom};
-use std::fs::File;
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
 use anyhow::{anyhow, bail};
+use crate::charset::Charset;
+use crate::document::{Document, SCAN_CHUNK_SIZE};
 use crate::options::Config;
+use crate::screen::Screen;
+use crate::template::Field;
 use crate::util;
 
+/// The minimum width of the offset pane.
 const OFFSET_PANE_WIDTH: i32 = 8;
-const HEX_PANE_WIDTH: i32 = 32 + 7;
-const CANON_PANE_WIDTH: i32 = 16;
-const SEP_WIDTH: i32 = 3;
 
-const SEP: &str = " | ";
+/// The default separator between the panes.
+pub const DEFAULT_SEPARATOR: &str = " | ";
+
+/// Color pairs used by the hex view.
+const COLOR_OFFSET: i16 = 1;
+const COLOR_ZERO: i16 = 2;
+const COLOR_PRINTABLE: i16 = 3;
+const COLOR_NONPRINTABLE: i16 = 4;
+const COLOR_HIGH: i16 = 5;
+const COLOR_CURSOR: i16 = 6;
+const COLOR_MATCH: i16 = 7;
+
+const INSPECTOR_HEIGHT: i32 = 8;
+const INSPECTOR_WIDTH: i32 = 56;
+
+/// The maximum number of bytes of a template field shown.
+const FIELD_VALUE_BYTES: u64 = 32;
+
 
 
 /// Directions
@@ -21,6 +43,262 @@ pub enum Direction {
 }
 
 
+/// The bytes interpreted as various types, in a single byte order.
+/// A value is None if there are not enough bytes left in the file.
+pub struct Interpretation {
+    pub u8: Option<u8>,
+    pub i8: Option<i8>,
+    pub u16: Option<u16>,
+    pub i16: Option<i16>,
+    pub u32: Option<u32>,
+    pub i32: Option<i32>,
+    pub u64: Option<u64>,
+    pub i64: Option<i64>,
+    pub f32: Option<f32>,
+    pub f64: Option<f64>,
+}
+
+impl Interpretation {
+    /// Interprets the start of the bytes, either as little-endian or big-endian.
+    pub fn new(bytes: &[u8], little_endian: bool) -> Self
+    {
+        Self {
+            u8: bytes.first().copied(),
+            i8: bytes.first().map(|b| *b as i8),
+            u16: le_array(bytes, little_endian).map(u16::from_le_bytes),
+            i16: le_array(bytes, little_endian).map(i16::from_le_bytes),
+            u32: le_array(bytes, little_endian).map(u32::from_le_bytes),
+            i32: le_array(bytes, little_endian).map(i32::from_le_bytes),
+            u64: le_array(bytes, little_endian).map(u64::from_le_bytes),
+            i64: le_array(bytes, little_endian).map(i64::from_le_bytes),
+            f32: le_array(bytes, little_endian).map(f32::from_le_bytes),
+            f64: le_array(bytes, little_endian).map(f64::from_le_bytes),
+        }
+    }
+
+    /// Returns the formatted values, one row per size of integer, with the signed or the unsigned
+    /// value first, followed by the floats.
+    pub fn rows(&self, signed: bool) -> Vec<InspectorRow>
+    {
+        let int_row = |unsigned: (&'static str, Option<String>), sign: (&'static str, Option<String>)| {
+            let (chosen, other) = if signed { (sign, unsigned) } else { (unsigned, sign) };
+            InspectorRow { name: chosen.0, value: chosen.1, other: Some(other) }
+        };
+        let float_row = |name, value| InspectorRow { name, value, other: None };
+
+        vec![
+            int_row(("u8", self.u8.map(|v| v.to_string())), ("i8", self.i8.map(|v| v.to_string()))),
+            int_row(("u16", self.u16.map(|v| v.to_string())), ("i16", self.i16.map(|v| v.to_string()))),
+            int_row(("u32", self.u32.map(|v| v.to_string())), ("i32", self.i32.map(|v| v.to_string()))),
+            int_row(("u64", self.u64.map(|v| v.to_string())), ("i64", self.i64.map(|v| v.to_string()))),
+            float_row("f32", self.f32.map(format_float)),
+            float_row("f64", self.f64.map(format_float)),
+        ]
+    }
+}
+
+/// A row of the inspector: a type name and its value, and for integers, the value with the other
+/// signedness, which is shown faintly.
+pub struct InspectorRow {
+    pub name: &'static str,
+    pub value: Option<String>,
+    pub other: Option<(&'static str, Option<String>)>,
+}
+
+/// Takes the first N bytes as a little-endian array, reversing them if they are big-endian.
+fn le_array<const N: usize>(bytes: &[u8], little_endian: bool) -> Option<[u8; N]>
+{
+    let mut array: [u8; N] = bytes.get(..N)?.try_into().ok()?;
+    if !little_endian {
+        array.reverse();
+    }
+
+    Some(array)
+}
+
+/// Formats a float, using the scientific notation for very large and very small values.
+fn format_float<T>(value: T) -> String
+where
+    T: Copy + Into<f64> + std::fmt::Display + std::fmt::LowerExp
+{
+    let abs = value.into().abs();
+    if abs == 0.0 || !abs.is_finite() || (1e-4..1e16).contains(&abs) {
+        format!("{}", value)
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+
+/// How the bytes are shown in the hex pane.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ByteFormat {
+    /// Two hex digits per byte, in groups of bytes.
+    Hex,
+    /// Eight binary digits per byte.
+    Bits,
+}
+
+impl ByteFormat {
+    /// Returns the number of characters a byte takes up.
+    fn cell_width(self) -> i32
+    {
+        match self {
+            ByteFormat::Hex => 2,
+            ByteFormat::Bits => 8,
+        }
+    }
+
+    /// Returns the number of bytes between the spaces, given the group size of hex bytes.
+    fn group_size(self, hex_group_size: i32) -> i32
+    {
+        match self {
+            ByteFormat::Hex => hex_group_size,
+            ByteFormat::Bits => 1,
+        }
+    }
+
+    /// Formats a byte.
+    fn format(self, byte: u8) -> String
+    {
+        match self {
+            ByteFormat::Hex => format!("{:02x}", byte),
+            ByteFormat::Bits => format!("{:08b}", byte),
+        }
+    }
+}
+
+
+/// The byte order of the multi-byte values.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// Returns the byte order with the given name.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name.to_ascii_lowercase().as_str() {
+            "le" => Some(Endianness::Little),
+            "be" => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the byte order.
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            Endianness::Little => "le",
+            Endianness::Big => "be",
+        }
+    }
+
+    /// Returns the name of the byte order, spelled out.
+    pub fn long_name(self) -> &'static str
+    {
+        match self {
+            Endianness::Little => "little-endian",
+            Endianness::Big => "big-endian",
+        }
+    }
+}
+
+
+/// The base in which the offsets are shown.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OffsetBase {
+    Hex,
+    Dec,
+    Oct,
+}
+
+impl OffsetBase {
+    /// Returns the base with the given name.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name.to_ascii_lowercase().as_str() {
+            "hex" => Some(OffsetBase::Hex),
+            "dec" => Some(OffsetBase::Dec),
+            "oct" => Some(OffsetBase::Oct),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the base.
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            OffsetBase::Hex => "hex",
+            OffsetBase::Dec => "dec",
+            OffsetBase::Oct => "oct",
+        }
+    }
+
+    /// Returns the base that follows in the cycle.
+    pub fn next(self) -> Self
+    {
+        match self {
+            OffsetBase::Hex => OffsetBase::Dec,
+            OffsetBase::Dec => OffsetBase::Oct,
+            OffsetBase::Oct => OffsetBase::Hex,
+        }
+    }
+
+    /// Returns the width of the offset pane, so that all offsets in a file of size `len` fit.
+    pub fn width(self, len: u64) -> i32
+    {
+        let digits = match self {
+            OffsetBase::Hex => format!("{:x}", len).len(),
+            OffsetBase::Dec => len.to_string().len(),
+            OffsetBase::Oct => format!("{:o}", len).len(),
+        };
+
+        OFFSET_PANE_WIDTH.max(digits as i32)
+    }
+
+    /// Formats an offset, zero-padded to `width`.
+    fn format(self, offset: u64, width: usize) -> String
+    {
+        match self {
+            OffsetBase::Hex => format!("{:0w$x}", offset, w = width),
+            OffsetBase::Dec => format!("{:0w$}", offset, w = width),
+            OffsetBase::Oct => format!("{:0w$o}", offset, w = width),
+        }
+    }
+}
+
+
+/// Returns the width of the hex pane, with one space between the groups of bytes.
+fn hex_pane_width(format: ByteFormat, group_size: i32, bytes_per_line: i32) -> i32
+{
+    (bytes_per_line * format.cell_width()) + ((bytes_per_line - 1) / format.group_size(group_size))
+}
+
+/// Returns the width taken up by the offset column and its separator. The column is hidden if
+/// `offset_width` is 0.
+fn offsets_width(offset_width: i32, sep_width: i32) -> i32
+{
+    if offset_width > 0 {
+        offset_width + sep_width
+    } else {
+        0
+    }
+}
+
+
+/// How bytes are highlighted, based on their values.
+#[derive(Clone, Copy, PartialEq)]
+pub enum HighlightScheme {
+    /// All bytes look the same.
+    None,
+    /// Null, printable, non-printable and high bytes look different.
+    ByteClass,
+}
+
+
 /// Types of hex view panes.
 enum HexPane {
     Hex,
@@ -28,317 +306,1981 @@ enum HexPane {
 }
 
 /// Editing modes in the hex view.
-enum HexEditingMode {
+#[derive(Clone, Copy, PartialEq)]
+pub enum HexEditingMode {
     Normal,
     Insert,
     Replace
 }
 
 
-/// The hex view object.
-pub struct HexView {
-    win: pancurses::Window,
-    offset_win: pancurses::Window,
-    hex_win: pancurses::Window,
-    canon_win: pancurses::Window,
-    statusline_win: pancurses::Window,
-    status: String,
-    oh_sep_win: pancurses::Window,
-    hc_sep_win: pancurses::Window,
-    cs_sep_win: pancurses::Window,
-    file: File,
-    active_pane: HexPane,
-    position_y: i32,
-    position_x: i32,
-    edit_mode: HexEditingMode,
-    buffer: Vec<u8>
+/// The subwindows of the hex view.
+struct Subwins<S: Screen> {
+    ruler_win: Option<S>,
+    offset_win: Option<S>,
+    hex_win: S,
+    canon_win: Option<S>,
+    statusline_win: S,
+    oh_sep_win: Option<S>,
+    hc_sep_win: S,
+    cs_sep_win: Option<S>
 }
 
-impl HexView {
-    /// Returns a new HexView.
-    pub fn new(win: pancurses::Window, f: File, config: &Config) -> Self
+impl<S: Screen> Subwins<S> {
+    /// Derives the subwindows from the dimensions of `win`.
+    /// The offset column is hidden if `offset_width` is 0. The canonical pane is hidden if it is
+    /// turned off, or the window is not wide enough for it.
+    fn new(
+        win: &S,
+        offset_width: i32,
+        hex_pane_width: i32,
+        sep_width: i32,
+        bytes_per_line: i32,
+        ruler: bool,
+        canon: bool
+    ) -> Self
     {
-        let mut widget = Self {
-            offset_win: win.derwin(
-                win.get_max_y() - 1,
-                OFFSET_PANE_WIDTH,
-                0,
-                0
-            ).expect("failed to create a subwin"),
+        let canon_pane_width = bytes_per_line;
+        let show_canon = canon && win.get_max_x()
+            >= offsets_width(offset_width, sep_width) + hex_pane_width + (2 * sep_width) + canon_pane_width;
+
+        // The ruler takes up the first line.
+        let top = ruler as i32;
+        let height = win.get_max_y() - 1 - top;
+        let hex_x = offsets_width(offset_width, sep_width);
+
+        Self {
+            ruler_win: if ruler {
+                Some(win.derwin(
+                    1,
+                    win.get_max_x(),
+                    0,
+                    0
+                ).expect("failed to create a subwin"))
+            } else {
+                None
+            },
+            offset_win: if offset_width > 0 {
+                Some(win.derwin(
+                    height,
+                    offset_width,
+                    top,
+                    0
+                ).expect("failed to create a subwin"))
+            } else {
+                None
+            },
             hex_win: win.derwin(
-                win.get_max_y() - 1,
-                HEX_PANE_WIDTH,
-                0,
-                SEP_WIDTH + OFFSET_PANE_WIDTH
-            ).expect("failed to create a subwin"),
-            canon_win: win.derwin(
-                win.get_max_y() - 1,
-                CANON_PANE_WIDTH,
-                0,
-                (SEP_WIDTH * 2) + OFFSET_PANE_WIDTH + HEX_PANE_WIDTH
+                height,
+                hex_pane_width,
+                top,
+                hex_x
             ).expect("failed to create a subwin"),
+            canon_win: if show_canon {
+                Some(win.derwin(
+                    height,
+                    canon_pane_width,
+                    top,
+                    hex_x + hex_pane_width + sep_width
+                ).expect("failed to create a subwin"))
+            } else {
+                None
+            },
             statusline_win: win.derwin(
                 1,
                 win.get_max_x(),
                 win.get_max_y() - 1,
                 0
             ).expect("failed to create a subwin"),
-            oh_sep_win: win.derwin(
-                win.get_max_y() - 1,
-                SEP_WIDTH,
-                0,
-                OFFSET_PANE_WIDTH
-            ).expect("failed to create a subwin"),
+            oh_sep_win: if offset_width > 0 {
+                Some(win.derwin(
+                    height,
+                    sep_width,
+                    top,
+                    offset_width
+                ).expect("failed to create a subwin"))
+            } else {
+                None
+            },
             hc_sep_win: win.derwin(
-                win.get_max_y() - 1,
-                SEP_WIDTH,
-                0,
-                OFFSET_PANE_WIDTH + SEP_WIDTH + HEX_PANE_WIDTH
-            ).expect("failed to create a subwin"),
-            cs_sep_win: win.derwin(
-                win.get_max_y() - 1,
-                SEP_WIDTH,
-                0,
-                OFFSET_PANE_WIDTH + (2 * SEP_WIDTH) + HEX_PANE_WIDTH + CANON_PANE_WIDTH
+                height,
+                sep_width,
+                top,
+                hex_x + hex_pane_width
             ).expect("failed to create a subwin"),
+            cs_sep_win: if show_canon {
+                Some(win.derwin(
+                    height,
+                    sep_width,
+                    top,
+                    hex_x + hex_pane_width + sep_width + canon_pane_width
+                ).expect("failed to create a subwin"))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+
+/// Deleted bytes are kept for undo only up to this many, so that deleting most of a huge file
+/// doesn't fill the memory. Larger deletions clear the history.
+const MAX_UNDO_DELETE: u64 = 16 * 1024 * 1024;
+
+/// What reverts a change, which is undone at once.
+enum Change {
+    /// Writing bytes back: the offsets of the written bytes, with their previous values.
+    Write(Vec<(u64, u8)>),
+    /// Removing inserted bytes, given by the offset and the count.
+    Remove(u64, u64),
+    /// Inserting deleted bytes back at the offset.
+    Restore(u64, Vec<u8>),
+    /// Several changes, reverted in this order.
+    Group(Vec<Change>),
+}
+
+impl Change {
+    /// Returns the lowest offset affected by the change.
+    fn offset(&self) -> u64
+    {
+        match self {
+            Change::Write(entries) => entries.iter().map(|e| e.0).min().unwrap_or(0),
+            Change::Remove(offset, _) | Change::Restore(offset, _) => *offset,
+            Change::Group(changes) => changes.iter().map(Change::offset).min().unwrap_or(0),
+        }
+    }
+}
+
+
+/// The hex view object.
+pub struct HexView<S: Screen = pancurses::Window> {
+    win: S,
+    ruler_win: Option<S>,
+    offset_win: Option<S>,
+    hex_win: S,
+    canon_win: Option<S>,
+    statusline_win: S,
+    inspector_win: Option<S>,
+    status: String,
+    name: String,
+    ro: bool,
+    oh_sep_win: Option<S>,
+    hc_sep_win: S,
+    cs_sep_win: Option<S>,
+    doc: Document,
+    active_pane: HexPane,
+    position_y: i32,
+    position_x: i32,
+    edit_mode: HexEditingMode,
+    buffer: Vec<u8>,
+    buffer_lines: i32,
+    entropy: f64,
+    size: u64,
+    dirty: bool,
+    undo_stack: VecDeque<Change>,
+    redo_stack: Vec<Change>,
+    undo_levels: usize,
+    highlight: Option<(u64, u64)>,
+    search_pattern: Option<Vec<Option<u8>>>,
+    matches: Vec<bool>,
+    bytes_per_line: i32,
+    line_width: i32,
+    group_size: i32,
+    byte_format: ByteFormat,
+    offset_base: OffsetBase,
+    offset_width: i32,
+    ruler: bool,
+    offsets: bool,
+    canon: bool,
+    wrap: bool,
+    separator: String,
+    box_separators: bool,
+    colors: bool,
+    highlight_scheme: HighlightScheme,
+    selection_start: Option<u64>,
+    pending_nibble: Option<u8>,
+    low_nibble: bool,
+    charset: Charset,
+    endianness: Endianness,
+    signed: bool,
+    template: Vec<Field>,
+    jump_position: Option<(usize, usize)>
+}
+
+impl<S: Screen> HexView<S> {
+    /// Returns a new HexView.
+    pub fn new(win: S, f: File, config: &Config) -> Self
+    {
+        let bytes_per_line = config.bytes_per_line as i32;
+        let size = f.metadata().map(|m| m.len()).unwrap_or(0);
+        let offset_width = if config.offsets { config.offset_base.width(size) } else { 0 };
+        let Subwins {
+            ruler_win,
+            offset_win,
+            hex_win,
+            canon_win,
+            statusline_win,
+            oh_sep_win,
+            hc_sep_win,
+            cs_sep_win
+        } = Subwins::new(
+            &win,
+            offset_width,
+            hex_pane_width(ByteFormat::Hex, config.group_size as i32, bytes_per_line),
+            config.separator.len() as i32,
+            bytes_per_line,
+            config.ruler,
+            config.canon
+        );
+
+        let mut widget = Self {
+            ruler_win,
+            offset_win,
+            hex_win,
+            canon_win,
+            statusline_win,
+            oh_sep_win,
+            hc_sep_win,
+            cs_sep_win,
+            inspector_win: None,
             status: String::new(),
+            name: config.infile_name.to_string_lossy().into_owned(),
+            ro: config.ro,
             active_pane: HexPane::Hex,
             win,
             position_y: 0,
             position_x: 0,
             edit_mode: HexEditingMode::Normal,
-            file: f,
-            buffer: Vec::new()
+            doc: Document::new(f, config.read_limit, config.mmap),
+            buffer: Vec::new(),
+            buffer_lines: 0,
+            entropy: 0.0,
+            size,
+            dirty: false,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            undo_levels: config.undo_levels,
+            highlight: None,
+            search_pattern: None,
+            matches: Vec::new(),
+            bytes_per_line,
+            line_width: bytes_per_line,
+            group_size: config.group_size as i32,
+            byte_format: ByteFormat::Hex,
+            offset_base: config.offset_base,
+            offset_width,
+            ruler: config.ruler,
+            offsets: config.offsets,
+            canon: config.canon,
+            wrap: config.wrap,
+            separator: config.separator.clone(),
+            box_separators: config.box_separators,
+            colors: false,
+            highlight_scheme: HighlightScheme::ByteClass,
+            selection_start: None,
+            pending_nibble: None,
+            low_nibble: false,
+            charset: config.charset,
+            endianness: Endianness::Little,
+            signed: false,
+            template: Vec::new(),
+            jump_position: None
         };
 
-        widget.status.push_str(format!("[{}]", config.infile_name.to_str().unwrap()).as_str());
-        if config.ro {
-            widget.status.push_str("[ro]");
-        }
+        widget.update_status();
 
         widget
     }
 
-    /// Returns the current position (seek) in the underlying file.
-    pub fn get_seek(&mut self) -> anyhow::Result<u64>
+    /// Replaces the window of the view and re-derives all subwindows from its new dimensions.
+    /// Returns the new seek.
+    pub fn resize(&mut self, win: S) -> anyhow::Result<u64>
     {
-        Ok(self.file.seek(SeekFrom::Current(0))?)
+        self.win = win;
+        self.rebuild()
     }
 
-    /// Writes a byte at the specified offset.
-    pub fn write_byte_at_offset(&mut self, byte: u8, offset: u64) -> anyhow::Result<usize>
+    /// Sets the number of bytes per line. Returns the new seek.
+    pub fn set_bytes_per_line(&mut self, bytes_per_line: i32) -> anyhow::Result<u64>
     {
-        let seek = self.get_seek()?;
-        self.file.seek(SeekFrom::Start(offset))?;
-        self.file.write_all(&[byte])?;
+        if Self::min_width(self.offset_width, self.sep_width(), ByteFormat::Hex, self.group_size, bytes_per_line) > self.win.get_max_x() {
+            bail!("{}: too many bytes per line for the window", bytes_per_line);
+        }
 
-        self.file.seek(SeekFrom::Start(seek))?;
+        self.line_width = bytes_per_line;
+        self.rebuild()
+    }
 
-        Ok(1)
+    /// Returns the number of bytes per line, as set. (fewer may be shown with binary bytes)
+    pub fn line_width(&self) -> i32
+    {
+        self.line_width
     }
 
-    /// Writes a byte at the specified [x, y] coordinates.
-    pub fn write_byte_at_position(&mut self, byte: u8, pos_y: i32, pos_x: i32) -> anyhow::Result<usize>
+    /// Shows or hides the ruler above the panes. Returns the new seek.
+    pub fn set_ruler(&mut self, ruler: bool) -> anyhow::Result<u64>
     {
-        let offset = match self.get_seek() {
-            Err(_) => return Ok(0),
-            Ok(v) => v
+        self.ruler = ruler;
+        self.rebuild()
+    }
+
+    /// Sets the number of hex bytes between the spaces. Returns the new seek.
+    pub fn set_group_size(&mut self, group_size: i32) -> anyhow::Result<u64>
+    {
+        if Self::min_width(self.offset_width, self.sep_width(), ByteFormat::Hex, group_size, self.line_width) > self.win.get_max_x() {
+            bail!("{}: the groups don't fit in the window", group_size);
+        }
+
+        self.group_size = group_size;
+        self.rebuild()
+    }
+
+    /// Returns the number of hex bytes between the spaces.
+    pub fn group_size(&self) -> i32
+    {
+        self.group_size
+    }
+
+    /// Shows or hides the offset column. Returns the new seek.
+    pub fn set_offsets(&mut self, offsets: bool) -> anyhow::Result<u64>
+    {
+        self.offsets = offsets;
+        self.rebuild()
+    }
+
+    /// Returns true if the offset column is shown.
+    pub fn has_offsets(&self) -> bool
+    {
+        self.offsets
+    }
+
+    /// Shows or hides the canonical pane. Returns the new seek.
+    pub fn set_canon(&mut self, canon: bool) -> anyhow::Result<u64>
+    {
+        self.canon = canon;
+        self.rebuild()
+    }
+
+    /// Returns true if the canonical pane is turned on. It can still be hidden in narrow windows.
+    pub fn has_canon(&self) -> bool
+    {
+        self.canon
+    }
+
+    /// Returns true if the ruler is shown.
+    pub fn has_ruler(&self) -> bool
+    {
+        self.ruler
+    }
+
+    /// Makes the cursor wrap around the ends of the file, instead of stopping at them.
+    pub fn set_wrap(&mut self, wrap: bool)
+    {
+        self.wrap = wrap;
+    }
+
+    /// Sets how the bytes are shown in the hex pane. Returns the new seek.
+    pub fn set_byte_format(&mut self, format: ByteFormat) -> anyhow::Result<u64>
+    {
+        self.byte_format = format;
+        self.rebuild()
+    }
+
+    /// Sets the base in which the offsets are shown. Returns the new seek.
+    pub fn set_offset_base(&mut self, base: OffsetBase) -> anyhow::Result<u64>
+    {
+        self.offset_base = base;
+        self.rebuild()
+    }
+
+    /// Returns the base in which the offsets are shown.
+    pub fn offset_base(&self) -> OffsetBase
+    {
+        self.offset_base
+    }
+
+    /// Re-derives all subwindows from the dimensions of the window and the byte format, keeping
+    /// the cursor on the same offset. Returns the new seek.
+    fn rebuild(&mut self) -> anyhow::Result<u64>
+    {
+        // The cursor can be past the end of the file.
+        let offset = self.cursor_offset()?.min(self.doc.len()?.saturating_sub(1));
+        let inspector = self.inspector_win.take().is_some();
+        self.offset_width = if self.offsets { self.offset_base.width(self.doc.len()?) } else { 0 };
+
+        // Binary bytes are wide, so only as many are shown as fit on a line, with the canonical
+        // pane if possible.
+        let width = self.win.get_max_x();
+        let sep_width = self.sep_width();
+        let canon_width = |n: i32| if self.canon { n + sep_width } else { 0 };
+        let bytes_per_line = match self.byte_format {
+            ByteFormat::Hex => self.line_width,
+            ByteFormat::Bits => (1..=self.line_width)
+                .rev()
+                .find(|n| Self::min_width(self.offset_width, sep_width, ByteFormat::Bits, 1, *n) + canon_width(*n) <= width)
+                .unwrap_or(1),
         };
 
-        // The offset of the current byte (under the cursor).
-        let byte_offset = offset + (pos_y as u64 * 16) + (pos_x as u64);
+        let subwins = Subwins::new(
+            &self.win,
+            self.offset_width,
+            hex_pane_width(self.byte_format, self.group_size, bytes_per_line),
+            sep_width,
+            bytes_per_line,
+            self.ruler,
+            self.canon
+        );
+        self.ruler_win = subwins.ruler_win;
+        self.offset_win = subwins.offset_win;
+        self.hex_win = subwins.hex_win;
+        self.canon_win = subwins.canon_win;
+        self.statusline_win = subwins.statusline_win;
+        self.oh_sep_win = subwins.oh_sep_win;
+        self.hc_sep_win = subwins.hc_sep_win;
+        self.cs_sep_win = subwins.cs_sep_win;
 
-        self.write_byte_at_offset(byte, byte_offset)
+        if self.colors {
+            if let Some(offset_win) = &self.offset_win {
+                offset_win.color_set(COLOR_OFFSET);
+            }
+        }
+
+        if self.canon_win.is_none() {
+            self.active_pane = HexPane::Hex;
+        }
+
+        self.win.erase();
+
+        // The lines have to be realigned, if their width has changed.
+        let seek = if bytes_per_line != self.bytes_per_line {
+            let seek = self.get_seek()?;
+            self.bytes_per_line = bytes_per_line;
+            self.jump_to(seek - seek % bytes_per_line as u64)?;
+            self.set_cursor_offset(offset)?
+        } else {
+            // The number of visible lines may have changed.
+            self.read_buf()?;
+            self.set_cursor_offset(offset)?
+        };
+
+        if inspector {
+            self.toggle_inspector().ok();
+        }
+
+        Ok(seek)
+    }
+
+    /// Returns the minimum width of the window, needed for the offsets and the hex pane.
+    /// An offset width of 0 means that the offsets are hidden.
+    pub fn min_width(offset_width: i32, sep_width: i32, format: ByteFormat, group_size: i32, bytes_per_line: i32) -> i32
+    {
+        offsets_width(offset_width, sep_width) + hex_pane_width(format, group_size, bytes_per_line) + sep_width
+    }
+
+    /// Returns the width of the separators between the panes.
+    fn sep_width(&self) -> i32
+    {
+        self.separator.len() as i32
+    }
+
+    /// Returns the minimum width of the window, needed for the current byte format.
+    pub fn required_width(&self) -> i32
+    {
+        match self.byte_format {
+            ByteFormat::Hex => {
+                Self::min_width(self.offset_width, self.sep_width(), ByteFormat::Hex, self.group_size, self.line_width)
+            },
+            ByteFormat::Bits => Self::min_width(self.offset_width, self.sep_width(), ByteFormat::Bits, 1, 1),
+        }
+    }
+
+    /// Rebuilds the status line text.
+    fn update_status(&mut self)
+    {
+        self.status.clear();
+        self.status.push_str(format!("[{}]", self.name).as_str());
+        if self.ro {
+            self.status.push_str("[ro]");
+        }
+        if self.dirty {
+            self.status.push_str("[+]");
+        }
+        if self.charset != Charset::Ascii {
+            self.status.push_str(format!("[{}]", self.charset.name()).as_str());
+        }
+        self.status.push_str(format!("[{}]", self.endianness.name()).as_str());
+        if let Some((index, len)) = self.jump_position {
+            self.status.push_str(format!("[{}/{}]", index, len).as_str());
+        }
+    }
+
+    /// Sets the position in the jumplist shown in the status line, as the index from 1 and the
+    /// length, or hides it.
+    pub fn set_jump_position(&mut self, position: Option<(usize, usize)>) -> anyhow::Result<()>
+    {
+        self.jump_position = position;
+        self.update_status();
+        self.draw_statusline()
+    }
+
+    /// Sets the character set of the canonical pane.
+    pub fn set_charset(&mut self, charset: Charset) -> anyhow::Result<()>
+    {
+        self.charset = charset;
+        self.update_status();
+        self.draw()
+    }
+
+    /// Returns the character set of the canonical pane.
+    pub fn charset(&self) -> Charset
+    {
+        self.charset
+    }
+
+    /// Sets the byte order of the values in the inspector.
+    pub fn set_endianness(&mut self, endianness: Endianness) -> anyhow::Result<()>
+    {
+        self.endianness = endianness;
+        self.update_status();
+        self.draw()
     }
 
-    /// Writes a byte at the position of the cursor.
-    pub fn write_byte_at_cursor(&mut self, byte: u8) -> anyhow::Result<usize>
-    {
-        let (y, x) = self.get_pos_yx();
-        self.write_byte_at_position(byte, y, x)
+    /// Returns the byte order of the values in the inspector.
+    pub fn endianness(&self) -> Endianness
+    {
+        self.endianness
+    }
+
+    /// Makes the inspector show the integers as signed first, or as unsigned.
+    pub fn set_signed(&mut self, signed: bool) -> anyhow::Result<()>
+    {
+        self.signed = signed;
+        self.draw()
+    }
+
+    /// Updates the size shown in the status line, after the size of the file has changed.
+    fn update_size(&mut self) -> anyhow::Result<()>
+    {
+        self.size = self.doc.len()?;
+        self.doc.remap();
+
+        // The size only changes with edits that are written through to the file, which are unsaved
+        // until the file is written.
+        self.dirty = true;
+        self.update_status();
+
+        Ok(())
+    }
+
+    /// Sets the fields of the template, which are shown when the cursor is on them.
+    pub fn set_template(&mut self, fields: Vec<Field>)
+    {
+        self.template = fields;
+    }
+
+    /// Returns the name and the decoded value of the template field under the cursor.
+    fn field_at_cursor(&self) -> anyhow::Result<Option<(String, String)>>
+    {
+        let offset = self.cursor_offset()?;
+        let field = match self.template.iter().rev().find(|f| f.contains(offset)) {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+
+        let bytes = self.doc.read_range(field.offset, field.size.min(FIELD_VALUE_BYTES) as usize)?;
+        let mut value = field.decode(&bytes);
+        if field.size > FIELD_VALUE_BYTES {
+            value.push_str("...");
+        }
+
+        Ok(Some((field.name.clone(), value)))
+    }
+
+    /// Returns the bytes of the file, with the pending edits.
+    pub fn document(&self) -> &Document
+    {
+        &self.doc
+    }
+
+    /// Returns the bytes of the file, with the pending edits, mutably.
+    pub fn document_mut(&mut self) -> &mut Document
+    {
+        &mut self.doc
+    }
+
+    /// Replaces the pending edits with recovered ones. Edits past the end of the file are dropped.
+    /// Returns the number of recovered edits.
+    pub fn restore_edits(&mut self, mut edits: HashMap<u64, u8>) -> anyhow::Result<usize>
+    {
+        let len = self.doc.len()?;
+        edits.retain(|offset, _| *offset < len);
+
+        self.dirty = !edits.is_empty();
+        self.doc.edits = edits;
+        self.update_status();
+        self.draw()?;
+
+        Ok(self.doc.edits.len())
+    }
+
+    /// Returns true if there are unsaved changes.
+    pub fn is_dirty(&self) -> bool
+    {
+        self.dirty
+    }
+
+    /// Returns the current position (seek) in the underlying file.
+    pub fn get_seek(&self) -> anyhow::Result<u64>
+    {
+        Ok((&self.doc.file).stream_position()?)
+    }
+
+    /// Writes all the pending edits to the disk. Returns the size of the file.
+    pub fn flush(&mut self) -> anyhow::Result<u64>
+    {
+        let seek = self.get_seek()?;
+
+        let mut offsets: Vec<u64> = self.doc.edits.keys().copied().collect();
+        offsets.sort_unstable();
+
+        for offset in offsets {
+            self.doc.file.seek(SeekFrom::Start(offset))?;
+            self.doc.file.write_all(&[self.doc.edits[&offset]])?;
+        }
+
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+        self.doc.file.sync_all()?;
+
+        self.doc.edits.clear();
+        self.dirty = false;
+        self.update_status();
+        self.read_buf()?;
+
+        Ok(self.doc.file.metadata()?.len())
+    }
+
+    /// Marks the file as read-only, or not, in the status line.
+    pub fn set_readonly(&mut self, ro: bool)
+    {
+        self.ro = ro;
+        self.update_status();
+    }
+
+    /// Reopens the underlying file as read-write, keeping the current position.
+    pub fn reopen(&mut self, path: &Path) -> anyhow::Result<()>
+    {
+        let seek = self.get_seek()?;
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+        file.seek(SeekFrom::Start(seek))?;
+
+        self.doc.file = file;
+        self.ro = false;
+        self.doc.remap();
+        self.update_status();
+
+        Ok(())
+    }
+
+    /// Switches to another file, which holds the contents of this one with the pending edits
+    /// written, keeping the current position.
+    pub fn switch_file(&mut self, mut file: File, path: &Path) -> anyhow::Result<()>
+    {
+        let seek = self.get_seek()?;
+        file.seek(SeekFrom::Start(seek))?;
+
+        self.doc.file = file;
+        self.doc.edits.clear();
+        self.name = path.to_string_lossy().into_owned();
+        self.ro = false;
+        self.update_size()?;
+        self.dirty = false;
+        self.update_status();
+
+        Ok(())
+    }
+
+    /// Replaces the underlying file with a temporary copy of it, keeping the pending edits, the
+    /// history and the current position.
+    pub fn copy_file(&mut self) -> anyhow::Result<()>
+    {
+        let seek = self.get_seek()?;
+        self.doc.file.seek(SeekFrom::Start(0))?;
+        let copied = util::copy_to_temp_file(&mut self.doc.file);
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+
+        let mut file = copied?;
+        file.seek(SeekFrom::Start(seek))?;
+        self.doc.file = file;
+        self.doc.remap();
+
+        Ok(())
+    }
+
+    /// Marks the changes as saved, when they were written somewhere else than the file.
+    pub fn set_saved(&mut self)
+    {
+        self.dirty = false;
+        self.update_status();
+    }
+
+    /// Returns the byte at the specified offset, with the pending edits applied.
+    pub fn byte_at_offset(&mut self, offset: u64) -> anyhow::Result<u8>
+    {
+        if let Some(byte) = self.doc.edits.get(&offset) {
+            return Ok(*byte);
+        }
+
+        let seek = self.get_seek()?;
+        self.doc.file.seek(SeekFrom::Start(offset))?;
+        let bytes = util::freadn_to_vec(&mut self.doc.file, 1)?;
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+
+        match bytes.first() {
+            Some(byte) => Ok(*byte),
+            None => Err(anyhow!("attempting to read beyond the end of the file")),
+        }
+    }
+
+    /// Writes a byte at the specified offset. The write is kept in memory until flushed.
+    pub fn write_byte_at_offset(&mut self, byte: u8, offset: u64) -> anyhow::Result<usize>
+    {
+        self.write_bytes_at_offset(&[byte], offset)
+    }
+
+    /// Writes bytes at the specified offset, as one change.
+    pub fn write_bytes_at_offset(&mut self, bytes: &[u8], offset: u64) -> anyhow::Result<usize>
+    {
+        if offset + bytes.len() as u64 > self.doc.len()? {
+            bail!("attempting to write beyond the end of the file");
+        }
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+
+        // Remember the previous values, so that the write can be undone.
+        let previous = self.doc.read_range(offset, bytes.len())?;
+        self.push_change(Change::Write(
+            previous.into_iter()
+                .enumerate()
+                .map(|(i, b)| (offset + i as u64, b))
+                .collect()
+        ));
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.stage_byte(*byte, offset + i as u64);
+        }
+
+        Ok(bytes.len())
+    }
+
+    /// Fills the range [start, end) with a byte, as one change. The previous values are read in
+    /// chunks.
+    pub fn fill(&mut self, start: u64, end: u64, byte: u8) -> anyhow::Result<usize>
+    {
+        if end > self.doc.len()? {
+            bail!("attempting to write beyond the end of the file");
+        }
+        if start >= end {
+            return Ok(0);
+        }
+
+        let mut previous = Vec::new();
+        let mut offset = start;
+        while offset < end {
+            let size = (end - offset).min(SCAN_CHUNK_SIZE);
+            let chunk = self.doc.read_range(offset, size as usize)?;
+            previous.extend(chunk.into_iter().enumerate().map(|(i, b)| (offset + i as u64, b)));
+            offset += size;
+        }
+        self.push_change(Change::Write(previous));
+
+        for offset in start..end {
+            self.stage_byte(byte, offset);
+        }
+        self.draw()?;
+
+        Ok((end - start) as usize)
+    }
+
+    /// Inserts a byte at the specified offset, shifting the rest of the file forward.
+    pub fn insert_byte_at_offset(&mut self, byte: u8, offset: u64) -> anyhow::Result<usize>
+    {
+        self.insert_bytes_at_offset(&[byte], offset)
+    }
+
+    /// Inserts bytes at the specified offset, shifting the rest of the file forward.
+    /// Unlike the writes, insertions go straight to the file, but they can be undone.
+    pub fn insert_bytes_at_offset(&mut self, bytes: &[u8], offset: u64) -> anyhow::Result<usize>
+    {
+        self.insert_raw(bytes, offset)?;
+        self.push_change(Change::Remove(offset, bytes.len() as u64));
+        self.draw()?;
+
+        Ok(bytes.len())
+    }
+
+    /// Inserts count zero bytes at the specified offset, shifting the rest of the file forward.
+    /// Unlike the writes, insertions go straight to the file, but they can be undone.
+    pub fn insert_zeros_at_offset(&mut self, offset: u64, count: u64) -> anyhow::Result<u64>
+    {
+        if count == 0 {
+            bail!("nothing to insert");
+        }
+        let len = self.doc.len()?;
+        if offset > len {
+            bail!("attempting to insert beyond the end of the file");
+        }
+
+        let seek = self.get_seek()?;
+
+        // The file grows with zeros, so only the bytes that were moved away need to be cleared.
+        self.make_room(offset, count)?;
+        let zeros = vec![0; SCAN_CHUNK_SIZE.min(count) as usize];
+        let mut cleared = offset;
+        let end = offset.saturating_add(count).min(len);
+        self.doc.file.seek(SeekFrom::Start(offset))?;
+        while cleared < end {
+            let size = (end - cleared).min(zeros.len() as u64) as usize;
+            self.doc.file.write_all(&zeros[..size])?;
+            cleared += size as u64;
+        }
+
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+        self.read_buf()?;
+
+        self.push_change(Change::Remove(offset, count));
+        self.draw()?;
+
+        Ok(count)
+    }
+
+    /// Inserts bytes at the specified offset in the file, without recording the change.
+    fn insert_raw(&mut self, bytes: &[u8], offset: u64) -> anyhow::Result<()>
+    {
+        let len = self.doc.len()?;
+        if offset > len {
+            bail!("attempting to insert beyond the end of the file");
+        }
+
+        let seek = self.get_seek()?;
+
+        self.make_room(offset, bytes.len() as u64)?;
+        self.doc.file.seek(SeekFrom::Start(offset))?;
+        self.doc.file.write_all(bytes)?;
+
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+        self.read_buf()?;
+
+        Ok(())
+    }
+
+    /// Grows the file by count bytes and moves everything from the offset to the end of the file
+    /// forward by count. The tail is moved in chunks of at most the read limit, starting from the
+    /// end.
+    fn make_room(&mut self, offset: u64, count: u64) -> anyhow::Result<()>
+    {
+        let len = self.doc.len()?;
+        let limit = self.doc.read_limit();
+        let new_len = match len.checked_add(count) {
+            Some(l) => l,
+            None => bail!("the file would be too large"),
+        };
+        self.doc.file.set_len(new_len)?;
+
+        let mut chunk_end = len;
+        while chunk_end > offset {
+            let chunk_start = chunk_end.saturating_sub(limit as u64).max(offset);
+            self.doc.file.seek(SeekFrom::Start(chunk_start))?;
+            let chunk = util::freadn_to_vec_chunked(&mut self.doc.file, (chunk_end - chunk_start) as usize, limit)?;
+            self.doc.file.seek(SeekFrom::Start(chunk_start + count))?;
+            self.doc.file.write_all(&chunk)?;
+            chunk_end = chunk_start;
+        }
+        self.update_size()?;
+
+        // The pending edits after the offset have moved as well.
+        self.shift_edits(offset, count as i64);
+
+        Ok(())
+    }
+
+    /// Writes bytes at the end of the file. Returns the offset they were written at.
+    /// Like insertions, they go straight to the file, but they can be undone.
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<u64>
+    {
+        let seek = self.get_seek()?;
+
+        let offset = self.doc.file.seek(SeekFrom::End(0))?;
+        self.doc.file.write_all(bytes)?;
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+        self.update_size()?;
+
+        self.push_change(Change::Remove(offset, bytes.len() as u64));
+        self.read_buf()?;
+        self.draw()?;
+
+        Ok(offset)
+    }
+
+    /// Writes everything read from a reader over the bytes at an offset, in chunks of at most the
+    /// read limit. The bytes within the file are kept in memory until flushed, the rest is
+    /// appended to the file. Both are undone at once. Returns the number of bytes read and the
+    /// number of them appended.
+    pub fn write_from_reader<R: Read>(&mut self, reader: &mut R, offset: u64) -> anyhow::Result<(u64, u64)>
+    {
+        let len = self.doc.len()?;
+        if offset > len {
+            bail!("attempting to write beyond the end of the file");
+        }
+
+        let seek = self.get_seek()?;
+        let mut buf = vec![0; self.doc.read_limit()];
+        let mut previous = Vec::new();
+        let mut position = offset;
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+
+            let overlap = (len.saturating_sub(position) as usize).min(read);
+            let bytes = self.doc.read_range(position, overlap)?;
+            previous.extend(bytes.into_iter().enumerate().map(|(i, b)| (position + i as u64, b)));
+            for (i, byte) in buf[..overlap].iter().enumerate() {
+                self.stage_byte(*byte, position + i as u64);
+            }
+
+            if overlap < read {
+                self.doc.file.seek(SeekFrom::End(0))?;
+                self.doc.file.write_all(&buf[overlap..read])?;
+            }
+            position += read as u64;
+        }
+        self.doc.file.seek(SeekFrom::Start(seek))?;
+
+        let appended = position.saturating_sub(len);
+        let mut changes = 0;
+        if !previous.is_empty() {
+            self.push_change(Change::Write(previous));
+            changes += 1;
+        }
+        if appended > 0 {
+            self.update_size()?;
+            self.push_change(Change::Remove(len, appended));
+            changes += 1;
+        }
+        self.group_changes(changes);
+
+        self.read_buf()?;
+        self.draw()?;
+
+        Ok((position - offset, appended))
+    }
+
+    /// Shrinks or grows the file to a size. Grown files are filled with zeros. Returns the new
+    /// seek. The file is resized straight away, but it can be undone.
+    pub fn truncate(&mut self, size: u64) -> anyhow::Result<u64>
+    {
+        let offset = self.cursor_offset()?.min(size.saturating_sub(1));
+        let len = self.doc.len()?;
+
+        if size == len {
+            return self.get_seek();
+        } else if size < len {
+            let deleted = self.delete_raw(size, len - size)?;
+            self.push_deletion(size, deleted);
+        } else {
+            self.doc.file.set_len(size)?;
+            self.update_size()?;
+            self.push_change(Change::Remove(len, size - len));
+        }
+
+        self.rebuild()?;
+        self.set_cursor_offset(offset)
+    }
+
+    /// Inserts a byte at the position of the cursor.
+    pub fn insert_byte_at_cursor(&mut self, byte: u8) -> anyhow::Result<usize>
+    {
+        let offset = self.cursor_offset()?;
+        self.insert_byte_at_offset(byte, offset)
+    }
+
+    /// Deletes the byte at the specified offset, shifting the rest of the file backward.
+    pub fn delete_byte_at_offset(&mut self, offset: u64) -> anyhow::Result<usize>
+    {
+        self.delete_bytes_at_offset(offset, 1)
+    }
+
+    /// Deletes bytes at the specified offset, shifting the rest of the file backward.
+    /// Unlike the writes, deletions go straight to the file, but they can be undone.
+    pub fn delete_bytes_at_offset(&mut self, offset: u64, count: u64) -> anyhow::Result<usize>
+    {
+        let len = self.doc.len()?;
+        if offset >= len {
+            bail!("attempting to delete beyond the end of the file");
+        }
+        let count = count.min(len - offset);
+
+        let deleted = self.delete_raw(offset, count)?;
+        self.push_deletion(offset, deleted);
+
+        // Keep the cursor on the same position, unless it ended up past the end.
+        let new_len = len - count;
+        if self.cursor_offset()? >= new_len && new_len > 0 {
+            self.set_cursor_offset(new_len - 1)?;
+        } else {
+            self.draw()?;
+        }
+
+        Ok(count as usize)
+    }
+
+    /// Deletes bytes at the specified offset in the file, without recording the change. Returns
+    /// the deleted bytes, with the pending edits applied, unless there are too many to keep.
+    fn delete_raw(&mut self, offset: u64, count: u64) -> anyhow::Result<Option<Vec<u8>>>
+    {
+        let len = self.doc.len()?;
+        let count = count.min(len.saturating_sub(offset));
+        let new_len = len - count;
+        let deleted = if count <= MAX_UNDO_DELETE {
+            Some(self.doc.read_range(offset, count as usize)?)
+        } else {
+            None
+        };
+
+        let seek = self.get_seek()?;
+
+        // Move everything after the bytes back over them, in chunks of at most the read limit,
+        // and shrink the file.
+        let limit = self.doc.read_limit();
+        let mut chunk_start = offset + count;
+        while chunk_start < len {
+            let size = (len - chunk_start).min(limit as u64) as usize;
+            self.doc.file.seek(SeekFrom::Start(chunk_start))?;
+            let chunk = util::freadn_to_vec_chunked(&mut self.doc.file, size, limit)?;
+            self.doc.file.seek(SeekFrom::Start(chunk_start - count))?;
+            self.doc.file.write_all(&chunk)?;
+            chunk_start += size as u64;
+        }
+        self.doc.file.set_len(new_len)?;
+        self.update_size()?;
+
+        // Forget the pending edits of the deleted bytes and move the rest back.
+        self.doc.edits.retain(|o, _| *o < offset || *o >= offset + count);
+        self.shift_edits(offset + count, -(count as i64));
+
+        // Stay within the file if its last line was deleted.
+        let last_line = new_len.saturating_sub(1) - new_len.saturating_sub(1) % self.bytes_per_line as u64;
+        self.doc.file.seek(SeekFrom::Start(seek.min(last_line)))?;
+        self.read_buf()?;
+
+        Ok(deleted)
+    }
+
+    /// Records a deletion, or clears the history if the deleted bytes couldn't be kept.
+    fn push_deletion(&mut self, offset: u64, deleted: Option<Vec<u8>>)
+    {
+        match deleted {
+            Some(bytes) => self.push_change(Change::Restore(offset, bytes)),
+            None => {
+                self.undo_stack.clear();
+                self.redo_stack.clear();
+            },
+        }
+    }
+
+    /// Replaces the bytes in the range [start, end) with other bytes, resizing the file if the
+    /// lengths differ. The replacement is undone at once.
+    pub fn replace_range(&mut self, start: u64, end: u64, bytes: &[u8]) -> anyhow::Result<()>
+    {
+        let range_len = (end - start) as usize;
+        let common = range_len.min(bytes.len());
+
+        let mut changes = 0;
+        if common > 0 {
+            self.write_bytes_at_offset(&bytes[..common], start)?;
+            changes += 1;
+        }
+
+        if bytes.len() > range_len {
+            self.insert_bytes_at_offset(&bytes[common..], start + common as u64)?;
+            changes += 1;
+        } else if bytes.len() < range_len {
+            self.delete_bytes_at_offset(start + common as u64, (range_len - common) as u64)?;
+            changes += 1;
+        } else {
+            self.draw()?;
+        }
+        self.group_changes(changes);
+
+        Ok(())
+    }
+
+    /// Deletes the byte under the cursor.
+    pub fn delete_byte_at_cursor(&mut self) -> anyhow::Result<usize>
+    {
+        let offset = self.cursor_offset()?;
+        self.delete_byte_at_offset(offset)
+    }
+
+    /// Deletes bytes at the cursor.
+    pub fn delete_bytes_at_cursor(&mut self, count: u64) -> anyhow::Result<usize>
+    {
+        let offset = self.cursor_offset()?;
+        self.delete_bytes_at_offset(offset, count)
+    }
+
+    /// Moves all the pending edits at or after an offset by the amount.
+    fn shift_edits(&mut self, from: u64, amount: i64)
+    {
+        let shift = |offset: u64| {
+            if offset >= from {
+                (offset as i64 + amount) as u64
+            } else {
+                offset
+            }
+        };
+
+        self.doc.edits = self.doc.edits.drain()
+            .map(|(offset, byte)| (shift(offset), byte))
+            .collect();
+    }
+
+    /// Records what reverts a new change, which makes the changes that were undone unreachable.
+    fn push_change(&mut self, change: Change)
+    {
+        self.undo_stack.push_back(change);
+        if self.undo_stack.len() > self.undo_levels {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Merges the last count changes into one, which is undone at once.
+    fn group_changes(&mut self, count: usize)
+    {
+        if count < 2 || self.undo_stack.len() < count {
+            return;
+        }
+
+        // The last change is reverted first.
+        let changes: Vec<Change> = self.undo_stack.drain(self.undo_stack.len() - count..).rev().collect();
+        self.undo_stack.push_back(Change::Group(changes));
+    }
+
+    /// Reverts the last change and moves the cursor to it. Returns the new seek.
+    pub fn undo(&mut self) -> anyhow::Result<u64>
+    {
+        let change = match self.undo_stack.pop_back() {
+            Some(v) => v,
+            None => bail!("already at oldest change"),
+        };
+
+        match self.apply_change(&change)? {
+            Some(redo) => self.redo_stack.push(redo),
+            None => self.redo_stack.clear(),
+        }
+
+        self.rebuild()?;
+        self.set_cursor_offset(change.offset().min(self.doc.len()?.saturating_sub(1)))
+    }
+
+    /// Reapplies the last undone change and moves the cursor to it. Returns the new seek.
+    pub fn redo(&mut self) -> anyhow::Result<u64>
+    {
+        let change = match self.redo_stack.pop() {
+            Some(v) => v,
+            None => bail!("already at newest change"),
+        };
+
+        match self.apply_change(&change)? {
+            Some(undo) => self.undo_stack.push_back(undo),
+            None => self.undo_stack.clear(),
+        }
+
+        self.rebuild()?;
+        self.set_cursor_offset(change.offset().min(self.doc.len()?.saturating_sub(1)))
+    }
+
+    /// Applies a change. Returns the change that reverts it, or None if the bytes it deletes are
+    /// too many to keep.
+    fn apply_change(&mut self, change: &Change) -> anyhow::Result<Option<Change>>
+    {
+        match change {
+            Change::Write(entries) => {
+                // The bytes in the file, as the edits that bring them back are dropped.
+                let start = change.offset();
+                let end = entries.iter().map(|e| e.0 + 1).max().unwrap_or(start);
+                let limit = self.doc.read_limit();
+                let seek = self.get_seek()?;
+                self.doc.file.seek(SeekFrom::Start(start))?;
+                let original = util::freadn_to_vec_chunked(&mut self.doc.file, (end - start) as usize, limit)?;
+                self.doc.file.seek(SeekFrom::Start(seek))?;
+
+                let mut reverse = Vec::with_capacity(entries.len());
+                for (offset, byte) in entries {
+                    reverse.push((*offset, self.byte_at_offset(*offset)?));
+                    if original.get((offset - start) as usize) == Some(byte) {
+                        self.doc.edits.remove(offset);
+                    } else {
+                        self.stage_byte(*byte, *offset);
+                    }
+                }
+                Ok(Some(Change::Write(reverse)))
+            },
+            Change::Remove(offset, count) => {
+                let deleted = self.delete_raw(*offset, *count)?;
+                Ok(deleted.map(|bytes| Change::Restore(*offset, bytes)))
+            },
+            Change::Restore(offset, bytes) => {
+                self.insert_raw(bytes, *offset)?;
+                Ok(Some(Change::Remove(*offset, bytes.len() as u64)))
+            },
+            Change::Group(changes) => {
+                let mut reverse = Vec::with_capacity(changes.len());
+                for change in changes {
+                    match self.apply_change(change)? {
+                        Some(r) => reverse.push(r),
+                        None => return Ok(None),
+                    }
+                }
+                // The reverse of the last change is applied first.
+                reverse.reverse();
+                Ok(Some(Change::Group(reverse)))
+            },
+        }
+    }
+
+    /// Highlights a range of bytes, given by the offset and the length.
+    pub fn set_highlight(&mut self, highlight: Option<(u64, u64)>)
+    {
+        self.highlight = highlight;
+    }
+
+    /// Returns the highlighted range of bytes, as the offset and the length.
+    pub fn highlight(&self) -> Option<(u64, u64)>
+    {
+        self.highlight
+    }
+
+    /// Returns the pattern whose matches are highlighted on the screen.
+    pub fn search_pattern(&self) -> Option<Vec<Option<u8>>>
+    {
+        self.search_pattern.clone()
+    }
+
+    /// Sets the pattern whose matches are highlighted on the screen, or clears it.
+    pub fn set_search_pattern(&mut self, pattern: Option<Vec<Option<u8>>>)
+    {
+        self.search_pattern = pattern;
+    }
+
+    /// Marks the bytes of the buffer that are part of a match of the search pattern.
+    fn find_matches(&self, buffer: &[u8]) -> Vec<bool>
+    {
+        let mut matches = vec![false; buffer.len()];
+        let needle = match &self.search_pattern {
+            Some(n) if !n.is_empty() => n,
+            _ => return matches,
+        };
+
+        for (i, window) in buffer.windows(needle.len()).enumerate() {
+            if util::matches_pattern(window, needle) {
+                matches[i..i + needle.len()].fill(true);
+            }
+        }
+
+        matches
+    }
+
+    /// Puts a byte into the pending edits.
+    fn stage_byte(&mut self, byte: u8, offset: u64)
+    {
+        self.doc.edits.insert(offset, byte);
+        self.dirty = true;
+        self.update_status();
+    }
+
+    /// Writes a byte at the specified [x, y] coordinates.
+    pub fn write_byte_at_position(&mut self, byte: u8, pos_y: i32, pos_x: i32) -> anyhow::Result<usize>
+    {
+        let offset = match self.get_seek() {
+            Err(_) => return Ok(0),
+            Ok(v) => v
+        };
+
+        // The offset of the current byte (under the cursor).
+        let byte_offset = offset + (pos_y as u64 * self.bytes_per_line as u64) + (pos_x as u64);
+
+        self.write_byte_at_offset(byte, byte_offset)
+    }
+
+    /// Writes a byte at the position of the cursor.
+    pub fn write_byte_at_cursor(&mut self, byte: u8) -> anyhow::Result<usize>
+    {
+        let (y, x) = self.get_pos_yx();
+        self.write_byte_at_position(byte, y, x)
+    }
+
+    /// Jumps to a position in the file, aligned on the start of a line.
+    /// The cursor is advanced to its correct position.
+    /// If the offset is negative, jumps from the end.
+    pub fn seek(&mut self, offset: i64) -> anyhow::Result<u64>
+    {
+        let mut real_offset: u64 = offset as u64;
+
+        // Jump from the end.
+        if offset.is_negative() {
+            let cur_seek = self.get_seek()?;
+
+            let end = self.doc.file.seek(SeekFrom::End(0))?;
+            // Jumping past the beginning stops at the beginning. (e.g. in an empty file)
+            real_offset = (end as i64 + offset).max(0) as u64;
+
+            self.doc.file.seek(SeekFrom::Start(cur_seek))?;
+        }
+
+        let remainder = real_offset % self.bytes_per_line as u64;
+        real_offset -= remainder;
+
+        // Update the cursor position and jump to the real offset.
+        let (orig_y, orig_x) = (self.position_y, self.position_x);
+        self.position_y = 0;
+        self.position_x = remainder as i32;
+        if let Err(e) = self.jump_to(real_offset) {
+            self.position_y = orig_y;
+            self.position_x = orig_x;
+            return Err(e);
+        }
+
+        Ok(real_offset)
+    }
+
+    /// Moves the cursor to an absolute offset, jumping to it if it's not visible.
+    /// Returns the new seek.
+    pub fn set_cursor_offset(&mut self, offset: u64) -> anyhow::Result<u64>
+    {
+        self.low_nibble = false;
+        let seek = self.get_seek()?;
+        let bytes_per_line = self.bytes_per_line as u64;
+        let visible = self.hex_win.get_max_y() as u64 * bytes_per_line;
+
+        if offset >= seek && offset < seek + visible {
+            self.position_y = ((offset - seek) / bytes_per_line) as i32;
+            self.position_x = ((offset - seek) % bytes_per_line) as i32;
+            self.draw()?;
+            Ok(seek)
+        } else {
+            self.seek(offset as i64)
+        }
+    }
+
+    /// Shows the file from a seek, with the cursor at an offset in the view.
+    pub fn set_view(&mut self, seek: u64, cursor: u64) -> anyhow::Result<()>
+    {
+        self.jump_to(seek)?;
+        self.set_cursor_offset(cursor)?;
+
+        Ok(())
+    }
+
+    /// Scrolls down or up by count. returns the new seek or error.
+    pub fn scroll(&mut self, direction: Direction, count: u32) -> anyhow::Result<u64>
+    {
+        let cur_seek = self.get_seek()?;
+
+        // Scrolling - jumping whole lines up or down.
+        let real_count = (count as u64).saturating_mul(self.bytes_per_line as u64);
+
+        match direction {
+            Direction::Down => {
+                Ok(self.jump_to(cur_seek.saturating_add(real_count))?)
+            },
+            Direction::Up => {
+                if real_count > cur_seek {
+                    Err(anyhow!("attempting to scroll up past beginning of the file"))
+                } else {
+                    Ok(self.jump_to(cur_seek - real_count)?)
+                }
+            },
+            Direction::Left => Err(anyhow!("cannot scroll left")),
+            Direction::Right => Err(anyhow!("cannot scroll right")),
+        }
+    }
+
+    /// Scrolls down or up by count screens, stopping at the beginning or the last line of the file.
+    /// The cursor keeps its position on the screen. Returns the new seek.
+    pub fn scroll_page(&mut self, direction: Direction, count: u32) -> anyhow::Result<u64>
+    {
+        let cur_seek = self.get_seek()?;
+        let bytes_per_line = self.bytes_per_line as u64;
+        let page = self.hex_win.get_max_y() as u64 * bytes_per_line * count as u64;
+
+        let seek = match direction {
+            Direction::Down => {
+                let last_line = (self.doc.len()?.saturating_sub(1) / bytes_per_line) * bytes_per_line;
+                (cur_seek + page).min(last_line).max(cur_seek)
+            },
+            Direction::Up => cur_seek.saturating_sub(page),
+            Direction::Left => bail!("cannot scroll left"),
+            Direction::Right => bail!("cannot scroll right"),
+        };
+
+        if seek == cur_seek {
+            return Ok(seek);
+        }
+
+        self.jump_to(seek)
+    }
+
+    /// Read to the buffer from the current seek.
+    pub fn read_buf(&mut self) -> anyhow::Result<()>
+    {
+        let bytes_to_read = self.hex_win.get_max_y() * self.bytes_per_line;
+        self.buffer = util::freadn_to_vec(&mut self.doc.file, bytes_to_read as usize)?;
+        self.buffer_lines = self.hex_win.get_max_y();
+
+        Ok(())
+    }
+
+    /// Fills the windows with formatted output. (based on internal variables)
+    pub fn draw(&mut self) -> anyhow::Result<()>
+    {
+        self.hex_win.mv(0, 0);
+        self.hc_sep_win.mv(0, 0);
+
+        // Get the the number of lines, the line width, and the current offset.
+        let nlines = self.hex_win.get_max_y();
+        let bytes_per_line = self.bytes_per_line;
+        let seek = self.get_seek()?;
+
+        // The buffer is stale, if the height of the view has changed since it was read.
+        if self.buffer_lines != nlines {
+            self.read_buf()?;
+        }
+
+        // Apply the pending edits on top of the bytes read from the file.
+        let mut buffer = self.buffer.clone();
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            if let Some(edit) = self.doc.edits.get(&(seek + i as u64)) {
+                *byte = *edit;
+            }
+        }
+
+        self.matches = self.find_matches(&buffer);
+        self.entropy = util::entropy(&buffer);
+        self.draw_statusline()?;
+
+        // Show the high nibble that is being typed over the byte under the cursor.
+        if let Some(nibble) = self.pending_nibble {
+            let index = (self.position_y * bytes_per_line + self.position_x) as usize;
+            if let Some(byte) = buffer.get_mut(index) {
+                *byte = (nibble << 4) | (*byte & 0x0f);
+            }
+        }
+
+        self.draw_ruler();
+
+        // Draw the seperators.
+        if let Some(oh_sep_win) = &self.oh_sep_win {
+            self.draw_separator(oh_sep_win, nlines);
+        }
+        self.draw_separator(&self.hc_sep_win, nlines);
+        if let Some(cs_sep_win) = &self.cs_sep_win {
+            self.draw_separator(cs_sep_win, nlines);
+        }
+
+        // Draw the offsets.
+        if let Some(offset_win) = &self.offset_win {
+            let offset_width = offset_win.get_max_x() as usize;
+            for i in 0..nlines as u64 {
+                let offset = seek + (i * bytes_per_line as u64);
+                offset_win.mvprintw(
+                    i as i32,
+                    0,
+                    format!("{}\n", self.offset_base.format(offset, offset_width))
+                );
+            }
+        }
+
+        // Draw the hex bytes.
+        let format = self.byte_format;
+        let group_size = format.group_size(self.group_size);
+        let blank = " ".repeat(format.cell_width() as usize);
+        for row in 0..nlines {
+            self.hex_win.mv(row, 0);
+            for byte in 0..bytes_per_line {
+                // Separate the byte groups.
+                if byte != 0 && byte % group_size == 0 {
+                    self.hex_win.printw(" ");
+                }
+
+                // Check if the byte is out of bounds.
+                match buffer.get((row * bytes_per_line + byte) as usize) {
+                    Some(b) => self.hex_win.printw(format.format(*b)),
+                    None => self.hex_win.printw(&blank),
+                };
+            }
+        }
+
+        // Draw the canonical view.
+        if let Some(canon_win) = &self.canon_win {
+            canon_win.mv(0, 0);
+            for row in 0..nlines {
+                for byte in 0..bytes_per_line {
+                    // Check if the character is out of bounds.
+                    let character = match buffer.get((row * bytes_per_line + byte) as usize) {
+                        Some(b) => self.charset.decode(*b).unwrap_or('.'),
+                        None => ' ',
+                    };
+
+                    canon_win.printw(character.to_string());
+                }
+            }
+        }
+
+        // Apply the colors and the highlighting to the bytes.
+        self.style_bytes(seek)?;
+        self.highlight_cursor();
+
+        self.draw_inspector()?;
+
+        Ok(())
+    }
+
+    /// Sets up the color pairs. Must only be called if the terminal supports colors.
+    pub fn init_colors(&mut self)
+    {
+        pancurses::init_pair(COLOR_OFFSET, pancurses::COLOR_GREEN, -1);
+        pancurses::init_pair(COLOR_ZERO, pancurses::COLOR_BLUE, -1);
+        pancurses::init_pair(COLOR_PRINTABLE, pancurses::COLOR_CYAN, -1);
+        pancurses::init_pair(COLOR_NONPRINTABLE, pancurses::COLOR_YELLOW, -1);
+        pancurses::init_pair(COLOR_HIGH, pancurses::COLOR_MAGENTA, -1);
+        pancurses::init_pair(COLOR_CURSOR, pancurses::COLOR_BLACK, pancurses::COLOR_WHITE);
+        pancurses::init_pair(COLOR_MATCH, pancurses::COLOR_BLACK, pancurses::COLOR_YELLOW);
+
+        if let Some(offset_win) = &self.offset_win {
+            offset_win.color_set(COLOR_OFFSET);
+        }
+        self.colors = true;
+    }
+
+    /// Returns the byte at the grid position, with the pending edits applied.
+    fn visible_byte(&self, seek: u64, y: i32, x: i32) -> Option<u8>
+    {
+        let index = y * self.bytes_per_line + x;
+        let byte = self.buffer.get(index as usize)?;
+
+        Some(*self.doc.edits.get(&(seek + index as u64)).unwrap_or(byte))
+    }
+
+    /// Sets how the bytes are highlighted based on their values.
+    pub fn set_highlight_scheme(&mut self, scheme: HighlightScheme) -> anyhow::Result<()>
+    {
+        self.highlight_scheme = scheme;
+        self.draw()
+    }
+
+    /// Returns the attributes and the color pair of the byte at the grid position.
+    fn byte_style(&self, seek: u64, y: i32, x: i32) -> (pancurses::chtype, i16)
+    {
+        let byte = match self.visible_byte(seek, y, x) {
+            Some(b) if self.highlight_scheme == HighlightScheme::ByteClass => b,
+            _ => return (pancurses::A_NORMAL, 0),
+        };
+
+        let (attributes, color) = if byte == 0 {
+            (pancurses::A_DIM, COLOR_ZERO)
+        } else if byte >= 0x80 {
+            (pancurses::A_BOLD, COLOR_HIGH)
+        } else if util::check_printable(byte) {
+            (pancurses::A_NORMAL, COLOR_PRINTABLE)
+        } else {
+            (pancurses::A_NORMAL, COLOR_NONPRINTABLE)
+        };
+
+        if self.colors {
+            (attributes, color)
+        } else {
+            (attributes, 0)
+        }
+    }
+
+    /// Starts a selection, anchored at the cursor.
+    pub fn start_selection(&mut self) -> anyhow::Result<()>
+    {
+        self.selection_start = Some(self.cursor_offset()?);
+        self.draw()
+    }
+
+    /// Cancels the selection.
+    pub fn clear_selection(&mut self) -> anyhow::Result<()>
+    {
+        self.selection_start = None;
+        self.draw()
+    }
+
+    /// Returns true if there is a selection.
+    pub fn has_selection(&self) -> bool
+    {
+        self.selection_start.is_some()
+    }
+
+    /// Returns the inclusive range of the selected bytes, if there is a selection.
+    pub fn selection(&self) -> anyhow::Result<Option<(u64, u64)>>
+    {
+        let start = match self.selection_start {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        let cursor = self.cursor_offset()?;
+
+        Ok(Some((start.min(cursor), start.max(cursor))))
+    }
+
+    /// Returns the range [start, end) of the visible bytes.
+    pub fn visible_range(&self) -> anyhow::Result<(u64, u64)>
+    {
+        let seek = self.get_seek()?;
+
+        Ok((seek, seek + self.buffer.len() as u64))
+    }
+
+    /// Returns the selected bytes, or the byte under the cursor if there is no selection.
+    pub fn yank(&self) -> anyhow::Result<Vec<u8>>
+    {
+        let (start, end) = match self.selection()? {
+            Some(range) => range,
+            None => {
+                let cursor = self.cursor_offset()?;
+                (cursor, cursor)
+            },
+        };
+
+        self.doc.read_range(start, (end - start + 1) as usize)
+    }
+
+    /// Applies the regular style to all the visible bytes.
+    fn style_bytes(&self, seek: u64) -> anyhow::Result<()>
+    {
+        let selection = self.selection()?;
+
+        for y in 0..self.hex_win.get_max_y() {
+            for x in 0..self.bytes_per_line {
+                self.style_byte(seek, y, x, selection);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the regular style of the byte at the grid position, in both panes.
+    fn style_byte(&self, seek: u64, y: i32, x: i32, selection: Option<(u64, u64)>)
+    {
+        let offset = seek + (y * self.bytes_per_line + x) as u64;
+        let (mut attributes, mut color) = self.byte_style(seek, y, x);
+        if self.matches.get((y * self.bytes_per_line + x) as usize) == Some(&true) {
+            if self.colors {
+                (attributes, color) = (pancurses::A_NORMAL, COLOR_MATCH);
+            } else {
+                attributes |= pancurses::A_UNDERLINE;
+            }
+        }
+        if let Some((start, len)) = self.highlight {
+            if offset >= start && offset < start + len {
+                attributes |= pancurses::A_REVERSE;
+            }
+        }
+        if let Some((start, end)) = selection {
+            if offset >= start && offset <= end {
+                attributes |= pancurses::A_REVERSE;
+            }
+        }
+
+        let (hex_y, hex_x) = self.hex_pos_to_cur(y, x);
+        self.hex_win.mvchgat(hex_y, hex_x, self.byte_format.cell_width(), attributes, color);
+        if let Some(canon_win) = &self.canon_win {
+            canon_win.mvchgat(y, x, 1, attributes, color);
+        }
+    }
+
+    /// Highlights the byte under the cursor, in both panes, overriding its regular style.
+    fn highlight_cursor(&self)
+    {
+        let (y, x) = (self.position_y, self.position_x);
+        let (attributes, color) = if self.colors {
+            (pancurses::A_BOLD, COLOR_CURSOR)
+        } else {
+            (pancurses::A_BOLD | pancurses::A_STANDOUT, 0)
+        };
+
+        let (hex_y, hex_x) = self.hex_pos_to_cur(y, x);
+        self.hex_win.mvchgat(hex_y, hex_x, self.byte_format.cell_width(), attributes, color);
+        if let Some(canon_win) = &self.canon_win {
+            canon_win.mvchgat(y, x, 1, attributes, color);
+        }
+    }
+
+    /// Refresh the window and all the subwindows.
+    pub fn refresh(&self)
+    {
+        self.win.refresh();
+        if let Some(ruler_win) = &self.ruler_win {
+            ruler_win.refresh();
+        }
+        if let Some(offset_win) = &self.offset_win {
+            offset_win.refresh();
+        }
+        self.hex_win.refresh();
+        if let Some(canon_win) = &self.canon_win {
+            canon_win.refresh();
+        }
+        self.statusline_win.refresh();
+        if let Some(oh_sep_win) = &self.oh_sep_win {
+            oh_sep_win.refresh();
+        }
+        self.hc_sep_win.refresh();
+        if let Some(cs_sep_win) = &self.cs_sep_win {
+            cs_sep_win.refresh();
+        }
+        if let Some(inspector_win) = &self.inspector_win {
+            inspector_win.refresh();
+        }
+    }
+
+    /// Interprets the bytes under the cursor as various integer and float types, in the byte order
+    /// of the inspector.
+    pub fn inspect(&self) -> anyhow::Result<Interpretation>
+    {
+        let bytes = self.doc.read_range(self.cursor_offset()?, 8)?;
+
+        Ok(Interpretation::new(&bytes, self.endianness == Endianness::Little))
+    }
+
+    /// Reads an unsigned integer of `width` bytes at the cursor, either as little-endian or
+    /// big-endian.
+    pub fn read_value_at_cursor(&self, width: usize, little_endian: bool) -> anyhow::Result<u64>
+    {
+        if !(1..=8).contains(&width) {
+            bail!("{}: invalid width (expected 1 to 8 bytes)", width);
+        }
+
+        let mut bytes = self.doc.read_range(self.cursor_offset()?, width)?;
+        if bytes.len() < width {
+            bail!("not enough bytes left in the file");
+        }
+        if !little_endian {
+            bytes.reverse();
+        }
+
+        Ok(bytes.iter().rev().fold(0, |value, byte| (value << 8) | *byte as u64))
+    }
+
+    /// Shows or hides the data inspector, drawn over the bottom of the view.
+    pub fn toggle_inspector(&mut self) -> anyhow::Result<()>
+    {
+        if self.inspector_win.take().is_none() {
+            let height = INSPECTOR_HEIGHT.min(self.hex_win.get_max_y());
+            let width = INSPECTOR_WIDTH.min(self.win.get_max_x());
+            let y = self.hex_win.get_beg_y() - self.win.get_beg_y() + self.hex_win.get_max_y() - height;
+            self.inspector_win = match self.win.derwin(height, width, y, 0) {
+                Ok(w) => Some(w),
+                Err(_) => bail!("not enough space for the inspector"),
+            };
+        }
+
+        self.win.erase();
+        self.draw()
+    }
+
+    /// Draws the data inspector, if it's shown.
+    fn draw_inspector(&mut self) -> anyhow::Result<()>
+    {
+        let inspector_win = match &self.inspector_win {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+
+        let result = self.inspect()?;
+        let format = |value: &Option<String>| value.clone().unwrap_or_else(|| String::from("-"));
+
+        inspector_win.erase();
+        inspector_win.mvprintw(0, 0, format!("{:<6}{:<50}", "", self.endianness.long_name()));
+        for (i, row) in result.rows(self.signed).iter().enumerate() {
+            let y = i as i32 + 1;
+            inspector_win.mvprintw(y, 0, format!("{:<6}{:<25}", row.name, format(&row.value)));
+            if let Some((name, value)) = &row.other {
+                inspector_win.printw(format!("{:<6}{:<19}", name, format(value)));
+                inspector_win.mvchgat(y, 31, -1, pancurses::A_DIM, 0);
+            }
+        }
+        if let Some((name, value)) = self.field_at_cursor()? {
+            inspector_win.mvprintw(INSPECTOR_HEIGHT - 1, 0, format!("{:<6}{} = {}", "field", name, value));
+        }
+        inspector_win.mvchgat(0, 0, -1, pancurses::A_REVERSE, 0);
+
+        Ok(())
     }
 
-    /// Jumps to a position in the file, aligned on 16-byte positions.
-    /// The cursor is advanced to its correct position.
-    /// If the offset is negative, jumps from the end.
-    pub fn seek(&mut self, offset: i64) -> anyhow::Result<u64>
+    /// Move the cursor. (automatically decides which pane) Returns the new seek.
+    pub fn move_cursor(&mut self, direction: Direction, count: i32) -> anyhow::Result<u64>
     {
-        let mut real_offset: u64 = offset as u64;
-
-        // Jump from the end.
-        if offset.is_negative() {
-            let cur_seek = self.get_seek()?;
+        let orig_seek = self.get_seek()?;
+        self.style_byte(orig_seek, self.position_y, self.position_x, None);
 
-            let end = self.file.seek(SeekFrom::End(0))?;
-            real_offset = (end as i64 + offset) as u64;
+        let mut result = Ok(());
+        for i in 0..count {
+            // With a count, move as far as possible, by whole bytes. At the ends of the file, the
+            // cursor stays put, unless it wraps around.
+            let moved = if count > 1 {
+                self.move_cursor_once(&direction).map(|_| ())
+            } else {
+                self.move_nibble_once(&direction)
+            };
+            if moved.is_err() {
+                if i == 0 && self.wrap {
+                    result = self.wrap_cursor(&direction);
+                }
+                break;
+            }
+        }
 
-            self.file.seek(SeekFrom::Start(cur_seek))?;
+        // The cursor is highlighted at the position it ended up at, even if wrapping failed.
+        // Scrolling redraws the view with the intermediate cursor position highlighted.
+        let seek = self.get_seek()?;
+        if seek != orig_seek {
+            self.draw()?;
+        } else if self.selection_start.is_some() {
+            self.style_bytes(seek)?;
         }
 
-        let remainder = real_offset % 16;
-        real_offset -= remainder;
+        self.highlight_cursor();
 
-        // Jump to the real offset and update the cursor position
-        self.jump_to(real_offset)?;
-        self.position_y = 0;
-        self.position_x = remainder as i32;
+        self.draw_statusline()?;
+        self.draw_inspector()?;
 
-        Ok(real_offset)
+        result.map(|_| seek)
     }
 
-    /// Scrolls down or up by count. returns the new seek or error.
-    pub fn scroll(&mut self, direction: Direction, count: u32) -> anyhow::Result<u64>
+    /// Moves the cursor to the other end of the file, as if it moved past the end it is at.
+    fn wrap_cursor(&mut self, direction: &Direction) -> anyhow::Result<()>
     {
-        let cur_seek = self.get_seek()?;
-
-        // Scrolling - jumping 16 bytes up or down.
-        let real_count = count * 16;
-
         match direction {
-            Direction::Down => {
-                Ok(self.jump_to(cur_seek + real_count as u64)?)
+            Direction::Up | Direction::Left => {
+                self.set_cursor_offset(self.doc.len()?.saturating_sub(1))?;
+                self.low_nibble = matches!(direction, Direction::Left);
             },
-            Direction::Up => {
-                if (cur_seek as i64 - real_count as i64) < 0 {
-                    Err(anyhow!("attempting to scroll up past beginning of the file"))
-                } else {
-                    Ok(self.jump_to(cur_seek - real_count as u64)?)
-                }
+            Direction::Down | Direction::Right => {
+                self.set_cursor_offset(0)?;
             },
-            Direction::Left => Err(anyhow!("cannot scroll left")),
-            Direction::Right => Err(anyhow!("cannot scroll right")),
         }
+
+        Ok(())
     }
 
-    /// Read to the buffer from the current seek.
-    pub fn read_buf(&mut self) -> anyhow::Result<()>
+    /// Moves the cursor to the beginning of the current line.
+    pub fn move_to_line_start(&mut self) -> anyhow::Result<()>
     {
-        let bytes_to_read = self.hex_win.get_max_y() * 16;
-        self.buffer = util::freadn_to_vec(&mut self.file, bytes_to_read as usize)?;
-
-        Ok(())
+        self.set_cursor_x(0)
     }
 
-    /// Fills the windows with formatted output. (based on internal variables)
-    pub fn draw(&mut self) -> anyhow::Result<()>
+    /// Moves the cursor to the last byte of the current line.
+    pub fn move_to_line_end(&mut self) -> anyhow::Result<()>
     {
-        self.offset_win.mv(0, 0);
-        self.hex_win.mv(0, 0);
-        self.canon_win.mv(0, 0);
-        self.oh_sep_win.mv(0, 0);
-        self.hc_sep_win.mv(0, 0);
-        self.cs_sep_win.mv(0, 0);
-        self.statusline_win.mv(0, 0);
+        let line_start = self.get_seek()? + (self.position_y * self.bytes_per_line) as u64;
+        let len = self.doc.len()?;
 
-        self.statusline_win.clear();
-        self.statusline_win.printw(self.status.as_str());
-        self.statusline_win.bkgd(pancurses::Attribute::Reverse);
-        
-        // Get the the number of lines and the current offset.
-        let nlines = self.offset_win.get_max_y();
+        // The last line can be partial.
+        let x = (len.saturating_sub(line_start + 1)).min(self.bytes_per_line as u64 - 1);
+
+        self.set_cursor_x(x as i32)
+    }
+
+    /// Moves the cursor to a column of the current line.
+    fn set_cursor_x(&mut self, x: i32) -> anyhow::Result<()>
+    {
         let seek = self.get_seek()?;
+        self.style_byte(seek, self.position_y, self.position_x, None);
 
-        // Draw the seperators.
-        for _ in 0..nlines {
-            self.oh_sep_win.printw(SEP);
-        }
-        for _ in 0..nlines {
-            self.hc_sep_win.printw(SEP);
-        }
-        for _ in 0..nlines {
-            self.cs_sep_win.printw(SEP);
-        }
+        self.position_x = x;
+        self.low_nibble = false;
 
-        // Draw the offsets.
-        for i in 0..nlines as u64 {
-            self.offset_win.mvprintw(i as i32, 0, format!("{:08x}\n", seek + (i * 16)));
+        if self.selection_start.is_some() {
+            self.style_bytes(seek)?;
         }
+        self.highlight_cursor();
 
-        // Draw the hex bytes.
-        for row in 0..nlines {
-            for pair in 0..8 {
-                if (row * 16 + pair * 2) % 16 != 0 {
-                    self.hex_win.printw(" ");
-                }
+        self.draw_statusline()?;
+        self.draw_inspector()
+    }
 
-                // Check if the first byte is out of bounds.
-                if row * 16 + pair * 2 >= self.buffer.len() as i32 {
-                    self.hex_win.printw("  ");
-                } else {
-                    self.hex_win.printw(
-                        format!("{:02x}", self.buffer[(row * 16 + pair * 2) as usize])
-                    );
-                }
+    /// Draws the column numbers above the hex and the canonical pane, if the ruler is shown.
+    fn draw_ruler(&self)
+    {
+        let ruler_win = match &self.ruler_win {
+            Some(w) => w,
+            None => return,
+        };
 
-                // Check if the second byte is out of bounds.
-                if row * 16 + pair * 2 + 1 >= self.buffer.len() as i32 {
-                    self.hex_win.printw("  ");
-                } else {
-                    self.hex_win.printw(
-                        format!("{:02x}", self.buffer[(row * 16 + pair * 2 + 1) as usize])
-                    );
-                }
+        ruler_win.erase();
+        for x in 0..self.bytes_per_line {
+            let (_, hex_x) = self.hex_pos_to_cur(0, x);
+            ruler_win.mvprintw(0, self.hex_win.get_beg_x() - ruler_win.get_beg_x() + hex_x, format!("{:02x}", x));
+            if let Some(canon_win) = &self.canon_win {
+                ruler_win.mvprintw(0, canon_win.get_beg_x() - ruler_win.get_beg_x() + x, format!("{:x}", x % 16));
             }
         }
+        ruler_win.mvchgat(0, 0, -1, pancurses::A_DIM, 0);
+    }
 
-        // Draw the canonical view.
-        for row in 0..nlines {
-            for byte in 0..16 {
-                let cur_byte;
-
-                // Check if the character is out of bounds.
-                if row * 16 + byte >= self.buffer.len() as i32 {
-                    cur_byte = b' ';
+    /// Draws the separator on the lines of a separator window. With box separators, the `|` are
+    /// drawn as vertical lines.
+    fn draw_separator(&self, win: &S, nlines: i32)
+    {
+        for y in 0..nlines {
+            win.mv(y, 0);
+            for c in self.separator.chars() {
+                if c == '|' && self.box_separators {
+                    win.addch(pancurses::ACS_VLINE());
                 } else {
-                    cur_byte = self.buffer[(row * 16 + byte) as usize];
+                    win.addch(c as pancurses::chtype);
                 }
-
-                let character = if util::check_printable(cur_byte) {
-                    cur_byte as char
-                } else {
-                    '.'
-                };
-                self.canon_win.addch(character);
             }
         }
-
-        Ok(())
     }
 
-    /// Refresh the window and all the subwindows.
-    pub fn refresh(&self)
+    /// Draws the status line, with the file info on the left and the cursor info on the right.
+    fn draw_statusline(&mut self) -> anyhow::Result<()>
     {
-        self.win.refresh();
-        self.offset_win.refresh();
-        self.hex_win.refresh();
-        self.canon_win.refresh();
-        self.statusline_win.refresh();
-        self.oh_sep_win.refresh();
-        self.hc_sep_win.refresh();
-        self.cs_sep_win.refresh();
-    }
+        let offset = self.cursor_offset()?;
+        let mut info = format!("{:#010x} ({})/{:#x} ({} bytes)", offset, offset, self.size, self.size);
 
-    /// Move the cursor. (automatically decides which pane)
-    pub fn move_cursor(&mut self, direction: Direction, count: i32) -> anyhow::Result<u64>
-    {
-        let (orig_y, orig_x) = (self.position_y, self.position_x);
-        // Get the current real position of the cursor. (relative to the pane)
-        let (hex_orig_y, hex_orig_x) = self.hex_pos_to_cur(orig_y, orig_x);
+        // The byte under the cursor, if the cursor is not past the end of the file.
+        if let Some(byte) = self.visible_byte(self.get_seek()?, self.position_y, self.position_x) {
+            info.push_str(format!("  {:02x}  {:08b}", byte, byte).as_str());
+        }
 
-        self.hex_win.mvchgat(hex_orig_y, hex_orig_x, 2, pancurses::A_NORMAL, 0);
-        self.canon_win.mvchgat(orig_y, orig_x, 1, pancurses::A_NORMAL, 0);
+        // The entropy of the visible bytes.
+        info.push_str(format!("  entropy {:.2}", self.entropy).as_str());
 
-        for _ in 0..(count) {
-            self.move_cursor_once(&direction)?;
+        // The template field under the cursor.
+        let mut status = self.status.clone();
+        if let Some((name, value)) = self.field_at_cursor()? {
+            status.push_str(format!("  {} = {}", name, value).as_str());
         }
 
-        let (y, x) = (self.position_y, self.position_x);
-        let (hex_y, hex_x) = self.hex_pos_to_cur(y, x);
-
-        self.hex_win.mvchgat(hex_y, hex_x, 2, pancurses::A_BOLD, 0);
-        self.canon_win.mvchgat(y, x, 1, pancurses::A_BOLD, 0);
+        self.statusline_win.clear();
+        self.statusline_win.mvprintw(0, 0, status.as_str());
+        let info_x = self.statusline_win.get_max_x() - info.len() as i32 - 1;
+        self.statusline_win.mvprintw(0, info_x.max(0), info.as_str());
+        self.statusline_win.bkgd(pancurses::A_REVERSE);
 
-        Ok(0)
+        Ok(())
     }
 
     /// Returns the absolute coordinates of the cursor. (based on the grid position)
@@ -347,41 +2289,95 @@ impl HexView {
         match self.active_pane {
             HexPane::Hex => {
                 let (y, x) = self.hex_pos_to_cur(self.position_y, self.position_x);
+                // The cursor is on the exact nibble.
+                let x = x + self.on_low_nibble() as i32;
                 (self.hex_win.get_beg_y() + y, self.hex_win.get_beg_x() + x)
             },
-            HexPane::Canon => {
-                (self.position_y + self.canon_win.get_beg_y(), self.position_x + self.canon_win.get_beg_x())
+            HexPane::Canon => match &self.canon_win {
+                Some(canon_win) => {
+                    (self.position_y + canon_win.get_beg_y(), self.position_x + canon_win.get_beg_x())
+                },
+                None => {
+                    let (y, x) = self.hex_pos_to_cur(self.position_y, self.position_x);
+                    (self.hex_win.get_beg_y() + y, self.hex_win.get_beg_x() + x)
+                },
             }
         }
     }
 
+    /// Sets the editing mode.
+    pub fn set_editing_mode(&mut self, mode: HexEditingMode)
+    {
+        self.edit_mode = mode;
+    }
+
+    /// Returns the editing mode.
+    pub fn editing_mode(&self) -> HexEditingMode
+    {
+        self.edit_mode
+    }
+
+    /// Returns the indicator of the editing mode, shown in the command line.
+    pub fn mode_indicator(&self) -> &'static str
+    {
+        match self.edit_mode {
+            HexEditingMode::Normal if self.has_selection() => "-- VISUAL --",
+            HexEditingMode::Normal => "-- NORMAL --",
+            HexEditingMode::Insert => "-- INSERT --",
+            HexEditingMode::Replace => "-- REPLACE --",
+        }
+    }
+
+    /// Sets the high nibble typed so far, shown at the cursor until the byte is complete.
+    pub fn set_pending_nibble(&mut self, nibble: Option<u8>) -> anyhow::Result<()>
+    {
+        self.pending_nibble = nibble;
+        self.draw()
+    }
+
     /// Returns the grid position of the cursor.
     pub fn get_pos_yx(&self) -> (i32, i32)
     {
         (self.position_y, self.position_x)
     }
 
+    /// Returns the absolute offset of the byte under the cursor.
+    pub fn cursor_offset(&self) -> anyhow::Result<u64>
+    {
+        Ok(self.get_seek()? + (self.position_y * self.bytes_per_line + self.position_x) as u64)
+    }
+
+    /// Returns true if the canonical pane is active.
+    pub fn in_canon_pane(&self) -> bool
+    {
+        matches!(self.active_pane, HexPane::Canon)
+    }
+
     /// Switches the active pane.
     pub fn switch_pane(&mut self) -> anyhow::Result<()>
     {
         match self.active_pane {
             HexPane::Hex => {
-                self.active_pane = HexPane::Canon;
+                // The canonical pane may be hidden.
+                if self.canon_win.is_some() {
+                    self.active_pane = HexPane::Canon;
+                }
             },
             HexPane::Canon => {
                 self.active_pane = HexPane::Hex;
             }
         }
 
-        Ok(self.draw()?)
+        self.draw()
     }
 
     /// Returns the window position of the cursor, based on the grid (virtual) position.
     pub fn hex_pos_to_cur(&self, y: i32, x:i32) -> (i32, i32)
     {
         let ret_y = y;
-        // Count the character position in the hex view. (with on space between byte pairs (like xxd))
-        let ret_x = (x * 2) + (x / 2);
+        // Count the character position in the hex view. (with one space between byte groups (like xxd))
+        let format = self.byte_format;
+        let ret_x = (x * format.cell_width()) + (x / format.group_size(self.group_size));
 
         (ret_y, ret_x)
     }
@@ -390,25 +2386,91 @@ impl HexView {
     fn jump_to(&mut self, offset: u64) -> anyhow::Result<u64>
     {
         let cur_seek = self.get_seek()?;
-        let end = self.file.seek(SeekFrom::End(0))?;
+        let end = self.doc.file.seek(SeekFrom::End(0))?;
+
+        // An empty file can only be viewed from its beginning.
+        if end == 0 && offset != 0 {
+            self.doc.file.seek(SeekFrom::Start(cur_seek))?;
+            bail!("the file is empty");
+        }
 
         if offset > end {
-            self.file.seek(SeekFrom::Start(cur_seek))?;
+            self.doc.file.seek(SeekFrom::Start(cur_seek))?;
             bail!("attempting to jump beyond the end of the file");
         }
 
-        let seek = self.file.seek(SeekFrom::Start(offset))?;
+        let seek = self.doc.file.seek(SeekFrom::Start(offset))?;
         self.read_buf()?;
         self.draw()?;
 
         Ok(seek)
     }
 
-    /// Moves the cursor in the hex view.
+    /// Moves the cursor by a nibble in the hex pane with hex bytes, or by a byte otherwise.
+    fn move_nibble_once(&mut self, direction: &Direction) -> anyhow::Result<()>
+    {
+        if !self.has_nibble_cursor() {
+            self.low_nibble = false;
+            self.move_cursor_once(direction)?;
+            return Ok(());
+        }
+
+        // Move within the byte first.
+        match direction {
+            Direction::Right if !self.low_nibble => {
+                self.low_nibble = true;
+                return Ok(());
+            },
+            Direction::Left if self.low_nibble => {
+                self.low_nibble = false;
+                return Ok(());
+            },
+            _ => (),
+        }
+
+        self.move_cursor_once(direction)?;
+        match direction {
+            Direction::Right => self.low_nibble = false,
+            Direction::Left => self.low_nibble = true,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the cursor moves over the nibbles: in the hex pane, with hex bytes.
+    fn has_nibble_cursor(&self) -> bool
+    {
+        matches!(self.active_pane, HexPane::Hex) && self.byte_format == ByteFormat::Hex
+    }
+
+    /// Returns true if the cursor is on the low nibble of a byte.
+    pub fn on_low_nibble(&self) -> bool
+    {
+        self.low_nibble && self.has_nibble_cursor()
+    }
+
+    /// Moves the cursor onto the high or the low nibble of the byte.
+    pub fn set_low_nibble(&mut self, low: bool)
+    {
+        self.low_nibble = low;
+    }
+
+    /// Moves the cursor by a byte, scrolling the view if needed. Returns the new seek.
     fn move_cursor_once(&mut self, direction: &Direction) -> anyhow::Result<u64>
     {
         let seek = self.get_seek()?;
 
+        // The cursor can go up to the end of the file, where bytes are appended.
+        let step = match direction {
+            Direction::Down => self.bytes_per_line as u64,
+            Direction::Right => 1,
+            Direction::Up | Direction::Left => 0,
+        };
+        if self.cursor_offset()? + step > self.doc.len()? {
+            bail!("attempting to move beyond the end of the file");
+        }
+
         // Match the directions and determine if the view needs to be scrolled based on the new
         // cursor position.
         match direction {
@@ -434,12 +2496,12 @@ impl HexView {
                         match self.scroll(Direction::Up, 1) {
                             Err(e) => Err(e),
                             Ok(o) => {
-                                self.position_x = 16 - 1;
+                                self.position_x = self.bytes_per_line - 1;
                                 Ok(o)
                             }
                         }
                     } else {
-                        self.position_x = 16 -1;
+                        self.position_x = self.bytes_per_line - 1;
                         self.position_y -= 1;
                         Ok(seek)
                     }
@@ -449,7 +2511,7 @@ impl HexView {
                 }
             },
             Direction::Right => {
-                if self.position_x == 16 - 1 {
+                if self.position_x == self.bytes_per_line - 1 {
                     if self.position_y + 1 == self.hex_win.get_max_y() {
                         match self.scroll(Direction::Down, 1) {
                             Err(e) => Err(e),
@@ -471,3 +2533,405 @@ impl HexView {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::screen::TestScreen;
+    use super::*;
+
+    /// The 37 bytes of a file with a partial last line and some unprintable bytes.
+    const FILE: &[u8] = b"Hello, world! ThisAis a est file.\0\x01\x02\xff";
+
+    /// Returns a view of a temporary file with the bytes, drawn on the screen.
+    fn view_on(screen: &TestScreen, bytes: &[u8]) -> HexView<TestScreen>
+    {
+        view_with(screen, bytes, &Config::default())
+    }
+
+    /// Returns a view of a temporary file with the bytes and the config, drawn on the screen.
+    fn view_with(screen: &TestScreen, bytes: &[u8], config: &Config) -> HexView<TestScreen>
+    {
+        let file = util::copy_to_temp_file(&mut Cursor::new(bytes)).unwrap();
+        let (lines, cols) = screen.get_max_yx();
+        let mut view = HexView::new(screen.derwin(lines, cols, 0, 0).unwrap(), file, config);
+        view.seek(0).unwrap();
+        view.draw().unwrap();
+        view
+    }
+
+    /// Returns a view of a temporary file with the bytes, on a screen of 24x80.
+    fn view(bytes: &[u8]) -> HexView<TestScreen>
+    {
+        view_on(&TestScreen::new(24, 80), bytes)
+    }
+
+    /// Returns the bytes of the view, with the pending edits applied.
+    fn contents(view: &HexView<TestScreen>) -> Vec<u8>
+    {
+        let doc = view.document();
+        doc.read_range(0, doc.len().unwrap() as usize).unwrap()
+    }
+
+    #[test]
+    fn insert_is_undone()
+    {
+        let mut view = view(b"abcdef");
+        view.insert_bytes_at_offset(b"XY", 2).unwrap();
+        assert_eq!(contents(&view), b"abXYcdef");
+        assert!(view.is_dirty());
+
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcdef");
+        view.redo().unwrap();
+        assert_eq!(contents(&view), b"abXYcdef");
+    }
+
+    #[test]
+    fn delete_is_undone_with_the_edits()
+    {
+        let mut view = view(b"abcdef");
+        view.write_byte_at_offset(b'Z', 3).unwrap();
+        view.delete_bytes_at_offset(2, 3).unwrap();
+        assert_eq!(contents(&view), b"abf");
+        assert!(view.is_dirty());
+
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcZef");
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcdef");
+        view.redo().unwrap();
+        view.redo().unwrap();
+        assert_eq!(contents(&view), b"abf");
+    }
+
+    #[test]
+    fn append_and_truncate_are_undone()
+    {
+        let mut view = view(b"abc");
+        view.append_bytes(b"de").unwrap();
+        view.truncate(1).unwrap();
+        assert_eq!(contents(&view), b"a");
+        view.truncate(3).unwrap();
+        assert_eq!(contents(&view), b"a\0\0");
+
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"a");
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcde");
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abc");
+    }
+
+    #[test]
+    fn replacement_is_undone_at_once()
+    {
+        let mut view = view(b"abcdef");
+        view.replace_range(1, 3, b"WXYZ").unwrap();
+        assert_eq!(contents(&view), b"aWXYZdef");
+        view.replace_range(1, 5, b"Q").unwrap();
+        assert_eq!(contents(&view), b"aQdef");
+
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"aWXYZdef");
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcdef");
+        view.redo().unwrap();
+        assert_eq!(contents(&view), b"aWXYZdef");
+    }
+
+    #[test]
+    fn seek_from_the_end_is_aligned()
+    {
+        let mut view = view(&[0; 37]);
+        assert_eq!(view.seek(-1).unwrap(), 32);
+        assert_eq!(view.get_seek().unwrap(), 32);
+        assert_eq!(view.cursor_offset().unwrap(), 36);
+    }
+
+    #[test]
+    fn empty_file_stays_at_the_start()
+    {
+        let mut view = view(&[]);
+        assert_eq!(view.seek(-1).unwrap(), 0);
+        assert_eq!(view.cursor_offset().unwrap(), 0);
+        assert!(view.seek(16).is_err());
+        assert_eq!(view.cursor_offset().unwrap(), 0);
+        assert_eq!(view.move_cursor(Direction::Down, 1).unwrap(), 0);
+    }
+
+
+    #[test]
+    fn partial_last_line_after_resize()
+    {
+        // Only the first two lines are visible at first.
+        let screen = TestScreen::new(3, 80);
+        let mut view = view_on(&screen, FILE);
+        assert_eq!(&screen.line(1)[11..50], "6973 4169 7320 6120 6573 7420 6669 6c65");
+
+        let screen = TestScreen::new(24, 80);
+        view.resize(screen.derwin(24, 80, 0, 0).unwrap()).unwrap();
+        view.draw().unwrap();
+        assert_eq!(&screen.line(2)[11..71], "2e00 0102 ff                            | .....            |");
+        assert_eq!(&screen.line(3)[11..71], "                                        |                  |");
+    }
+
+    #[test]
+    fn jump_position_stays_in_the_status_line()
+    {
+        let screen = TestScreen::new(24, 80);
+        let mut view = view_on(&screen, FILE);
+        view.set_jump_position(Some((2, 5))).unwrap();
+        view.move_cursor(Direction::Right, 1).unwrap();
+        assert!(screen.line(23).contains("[2/5]"));
+
+        view.set_jump_position(None).unwrap();
+        assert!(!screen.line(23).contains("[2/5]"));
+    }
+
+    #[test]
+    fn byte_format_keeps_the_cursor()
+    {
+        let mut view = view(FILE);
+        view.set_cursor_offset(20).unwrap();
+
+        view.set_byte_format(ByteFormat::Bits).unwrap();
+        assert_eq!(view.cursor_offset().unwrap(), 20);
+        view.set_byte_format(ByteFormat::Hex).unwrap();
+        assert_eq!(view.cursor_offset().unwrap(), 20);
+    }
+
+    #[test]
+    fn tail_is_moved_in_chunks()
+    {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let config = Config { read_limit: 7, ..Config::default() };
+        let mut view = view_with(&TestScreen::new(24, 80), &bytes, &config);
+
+        view.insert_bytes_at_offset(b"abc", 10).unwrap();
+        assert_eq!(&contents(&view)[..13], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, b'a', b'b', b'c']);
+        assert_eq!(&contents(&view)[13..], &bytes[10..]);
+
+        view.delete_bytes_at_offset(5, 8).unwrap();
+        assert_eq!(&contents(&view)[..], &[&bytes[..5], &bytes[10..]].concat()[..]);
+    }
+
+    #[test]
+    fn undone_writes_leave_no_edits()
+    {
+        let mut view = view(b"abcdef");
+        view.write_bytes_at_offset(b"XY", 1).unwrap();
+        view.write_byte_at_offset(b'Z', 2).unwrap();
+        assert_eq!(view.document().edits().len(), 2);
+
+        view.undo().unwrap();
+        assert_eq!(view.document().edits().len(), 2);
+        view.undo().unwrap();
+        assert!(view.document().edits().is_empty());
+        assert_eq!(contents(&view), b"abcdef");
+
+        view.redo().unwrap();
+        assert_eq!(contents(&view), b"aXYdef");
+    }
+
+    #[test]
+    fn count_moves_by_bytes()
+    {
+        let mut view = view(FILE);
+        view.move_cursor(Direction::Right, 1).unwrap();
+        assert_eq!(view.cursor_offset().unwrap(), 0);
+        assert!(view.on_low_nibble());
+        view.move_cursor(Direction::Right, 1).unwrap();
+        assert_eq!(view.cursor_offset().unwrap(), 1);
+        assert!(!view.on_low_nibble());
+
+        view.move_cursor(Direction::Right, 3).unwrap();
+        assert_eq!(view.cursor_offset().unwrap(), 4);
+        view.move_cursor(Direction::Left, 2).unwrap();
+        assert_eq!(view.cursor_offset().unwrap(), 2);
+    }
+
+    #[test]
+    fn replace_mode_stays_while_replacing()
+    {
+        let mut view = view(FILE);
+        view.set_editing_mode(HexEditingMode::Replace);
+
+        // As replace_many does, for each byte.
+        for (i, byte) in b"xyz".iter().enumerate() {
+            view.write_byte_at_cursor(*byte).unwrap();
+            view.set_low_nibble(true);
+            view.move_cursor(Direction::Right, 1).unwrap();
+            assert_eq!(view.mode_indicator(), "-- REPLACE --");
+            assert_eq!(view.cursor_offset().unwrap(), i as u64 + 1);
+            assert!(!view.on_low_nibble());
+        }
+        assert_eq!(&contents(&view)[..4], b"xyzl");
+
+        view.set_editing_mode(HexEditingMode::Normal);
+        assert_eq!(view.mode_indicator(), "-- NORMAL --");
+    }
+
+    #[test]
+    fn reader_is_written_in_chunks()
+    {
+        let config = Config { read_limit: 3, ..Config::default() };
+        let mut view = view_with(&TestScreen::new(24, 80), b"abcdef", &config);
+
+        assert_eq!(view.write_from_reader(&mut Cursor::new(b"WXYZ"), 4).unwrap(), (4, 2));
+        assert_eq!(contents(&view), b"abcdWXYZ");
+        assert_eq!(view.write_from_reader(&mut Cursor::new(b"12"), 0).unwrap(), (2, 0));
+        assert_eq!(contents(&view), b"12cdWXYZ");
+
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcdWXYZ");
+        view.undo().unwrap();
+        assert_eq!(contents(&view), b"abcdef");
+        assert!(view.document().edits().is_empty());
+    }
+
+    /// Returns the lines of the screen, without the trailing blanks.
+    fn lines(screen: &TestScreen) -> Vec<String>
+    {
+        (0..screen.get_max_y()).map(|y| screen.line(y).trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn draw_golden()
+    {
+        let screen = TestScreen::new(5, 80);
+        view_on(&screen, FILE);
+        assert_eq!(lines(&screen), [
+            "00000000 | 4865 6c6c 6f2c 2077 6f72 6c64 2120 5468 | Hello, world! Th |",
+            "00000010 | 6973 4169 7320 6120 6573 7420 6669 6c65 | isAis a est file |",
+            "00000020 | 2e00 0102 ff                            | .....            |",
+            "00000030 |                                         |                  |",
+            "[][le]               0x00000000 (0)/0x25 (37 bytes)  48  01001000  entropy 4.24",
+        ]);
+    }
+
+    #[test]
+    fn draw_golden_narrow_lines()
+    {
+        let screen = TestScreen::new(7, 50);
+        let config = Config { bytes_per_line: 7, group_size: 1, offset_base: OffsetBase::Dec, ..Config::default() };
+        view_with(&screen, FILE, &config);
+        assert_eq!(lines(&screen)[..6], [
+            "00000000 | 48 65 6c 6c 6f 2c 20 | Hello,  |",
+            "00000007 | 77 6f 72 6c 64 21 20 | world!  |",
+            "00000014 | 54 68 69 73 41 69 73 | ThisAis |",
+            "00000021 | 20 61 20 65 73 74 20 |  a est  |",
+            "00000028 | 66 69 6c 65 2e 00 01 | file... |",
+            "00000035 | 02 ff                | ..      |",
+        ]);
+    }
+
+    #[test]
+    fn copy_keeps_the_view_and_the_edits()
+    {
+        let bytes: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+        let mut view = view(&bytes);
+        view.set_cursor_offset(2000).unwrap();
+        view.write_byte_at_offset(0xaa, 3).unwrap();
+        let seek = view.get_seek().unwrap();
+
+        view.copy_file().unwrap();
+        assert_eq!(view.get_seek().unwrap(), seek);
+        assert_ne!(seek, 0);
+        assert_eq!(view.cursor_offset().unwrap(), 2000);
+        view.delete_byte_at_offset(0).unwrap();
+        assert_eq!(contents(&view), [&[1, 2, 0xaa], &bytes[4..]].concat());
+    }
+
+    #[test]
+    fn inspector_sign_extends()
+    {
+        let little = Interpretation::new(&[0xff, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], true);
+        assert_eq!(little.u8, Some(0xff));
+        assert_eq!(little.i8, Some(-1));
+        assert_eq!(little.u16, Some(0xfeff));
+        assert_eq!(little.i16, Some(-257));
+        let little = Interpretation::new(&[0xfe, 0xff], true);
+        assert_eq!(little.i16, Some(-2));
+        assert_eq!(little.i32, None);
+        let big = Interpretation::new(&[0xff, 0xfe], false);
+        assert_eq!(big.i16, Some(-2));
+
+        let min = i64::MIN.to_le_bytes();
+        assert_eq!(Interpretation::new(&min, true).i64, Some(i64::MIN));
+        assert_eq!(Interpretation::new(&i64::MIN.to_be_bytes(), false).i64, Some(i64::MIN));
+        assert_eq!(Interpretation::new(&min, true).u64, Some(1 << 63));
+    }
+
+    #[test]
+    fn inspector_rows_put_the_chosen_sign_first()
+    {
+        let interpretation = Interpretation::new(&[0xff, 0xff], true);
+        let first = |signed| {
+            let rows = interpretation.rows(signed);
+            let (other, other_value) = rows[1].other.clone().unwrap();
+            (rows[0].name, rows[0].value.clone().unwrap(), rows[1].name, other, other_value.unwrap())
+        };
+
+        assert_eq!(first(true), ("i8", "-1".to_string(), "i16", "u16", "65535".to_string()));
+        assert_eq!(first(false), ("u8", "255".to_string(), "u16", "i16", "-1".to_string()));
+        assert_eq!(interpretation.rows(true)[2].value, None);
+    }
+
+    #[test]
+    fn entropy_includes_the_edits()
+    {
+        let screen = TestScreen::new(24, 80);
+        let mut view = view_on(&screen, &[0; 16]);
+        view.draw().unwrap();
+        assert!(lines(&screen).last().unwrap().contains("entropy 0.00"));
+
+        view.write_byte_at_offset(1, 0).unwrap();
+        view.draw().unwrap();
+        assert!(lines(&screen).last().unwrap().contains("entropy 0.34"));
+    }
+
+    #[test]
+    fn inserting_too_many_or_no_zeros_fails()
+    {
+        let mut view = view(b"abc");
+        assert!(view.insert_zeros_at_offset(1, u64::MAX).is_err());
+        assert!(view.insert_zeros_at_offset(1, 0).is_err());
+        assert_eq!(contents(&view), b"abc");
+        assert!(!view.is_dirty());
+        assert!(view.undo().is_err());
+    }
+
+    #[test]
+    fn fill_is_one_change_within_the_file()
+    {
+        let mut view = view(FILE);
+        assert!(view.fill(0, u64::MAX, 0).is_err());
+        assert!(!view.is_dirty());
+
+        let len = FILE.len() as u64;
+        assert_eq!(view.fill(4, len, 0xaa).unwrap(), FILE.len() - 4);
+        assert_eq!(contents(&view), [&FILE[..4], &[0xaa; 33][..]].concat());
+        view.undo().unwrap();
+        assert_eq!(contents(&view), FILE);
+    }
+
+    #[test]
+    fn scrolling_a_huge_count_fails()
+    {
+        let mut view = view(FILE);
+        assert!(view.scroll(Direction::Down, u32::MAX).is_err());
+        assert!(view.scroll(Direction::Up, u32::MAX).is_err());
+        assert_eq!(view.get_seek().unwrap(), 0);
+    }
+
+    #[test]
+    fn truncating_to_the_size_changes_nothing()
+    {
+        let mut view = view(FILE);
+        view.truncate(FILE.len() as u64).unwrap();
+        assert_eq!(contents(&view), FILE);
+        assert!(!view.is_dirty());
+        assert!(view.undo().is_err());
+    }
+}